@@ -2,16 +2,30 @@ use super::AppEnv;
 use crate::{
     error, log_response_2xx_content, log_response_2xx_file, log_response_3xx_content,
     log_response_3xx_file, log_response_4xx, log_response_5xx,
-    utils::{self, ContentType, HTTPCode, Protocall},
+    utils::{self, error::ApiError, ContentType, HTTPCode, Method, Protocall},
     Request,
+    Response::{create_response, HttpResponse},
     Response::pre_built_resp::{
-        ContentNotSupported, ContentTypeRequired, InternalServerError, InvalidContentLength,
-        MethodNotAllowed, MethodNotSupported, Notfound404, PayloadTooLarge, ReaquestNotHttp,
-        RequestTimeout, UTF8Error,
+        AllowedMethods, BadRequest, ContentNotSupported, ContentTypeRequired, HeaderFieldsTooLarge,
+        InsufficientStorage, InternalServerError, InvalidContentLength, MethodNotAllowed, MethodNotSupported,
+        Notfound404, PayloadTooLarge, PermanentRedirect, ReaquestNotHttp, RequestTimeout, URITooLong, UTF8Error,
     },
 };
-use std::{collections::HashMap, net::TcpStream, sync::Arc};
-use Request::HttpRequest;
+use std::{borrow::Cow, net::TcpStream, sync::Arc};
+use Request::{HttpRequest, PathParams};
+/// Answer a request that failed before reaching a handler, then close the connection.
+fn send_error(conn: &Arc<TcpStream>, app_env: &AppEnv, mut resp: HttpResponse) {
+    resp.add_header("Host", &app_env.host);
+    let _ = resp.send_response(
+        conn.clone(),
+        utils::Protocall::HTTP1_1,
+        app_env.write_time_out,
+        app_env.cache.clone(),
+        None,
+        app_env.send_buffer_size
+    );
+    let _ = conn.shutdown(std::net::Shutdown::Both);
+}
 /// This function handles clients. For every request this function gets to run.
 pub(crate) fn Client(
     conn: Arc<TcpStream>,
@@ -21,203 +35,62 @@ pub(crate) fn Client(
 ) -> () {
     let req_map = match HttpRequest::from_tcp_stream(conn.clone(), app_env.clone()) {
         Ok(_req) => _req,
-        Err(e) => match e {
-            utils::error::ApiError::RequestBodyNotRead(_msg) => {
-                let mut resp = InternalServerError("Error happend while reading body.");
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::InvalidContentLength(_msg) => {
-                let mut resp = InvalidContentLength();
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::ContentNotSupported(_msg) => {
-                let mut resp = ContentNotSupported();
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::RequestTimedout(_msg) => {
-                let mut resp = RequestTimeout();
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::RequestDataNotUTF8(_msg) => {
-                let mut resp = UTF8Error();
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::ContentTypeRequired(_msg) => {
-                let mut resp = ContentTypeRequired();
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::MethodNotAllowed(_msg) => {
-                let mut resp = MethodNotAllowed(_msg.unwrap());
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::MethodNotSupported(_msg) => {
-                let mut resp = MethodNotSupported(_msg.unwrap());
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::ResourceNotFound(_msg) => {
-                let mut resp = Notfound404(_msg.unwrap());
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::PayloadTooLarge(_msg) => {
-                let size = app_env.maximum_pay_load_in_bytes / (1024 * 1024);
-                let mut resp = PayloadTooLarge(size);
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::RequestNotHttp(_msg) => {
-                let msg = _msg.unwrap();
-                let mut resp = ReaquestNotHttp(msg);
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::CannotWriteDataToDisk(_msg) => {
-                let mut resp = InternalServerError("Internal server error.");
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            utils::error::ApiError::RequestReadError(_msg) => {
-                let mut resp = InternalServerError("Error while reading request metadata.");
-                resp.add_header("Host", &app_env.host);
-                let _ = resp.send_response(
-                    conn.clone(),
-                    utils::Protocall::HTTP1_1,
-                    app_env.write_time_out,
-                    app_env.cache.clone(),
-                    None,
-                    app_env.send_buffer_size
-                );
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-            _ => {
-                let _ = conn.shutdown(std::net::Shutdown::Both);
-                return;
-            }
-        },
+        Err(e) => {
+            let resp = match e {
+                ApiError::RequestBodyNotRead(_msg) => InternalServerError("Error happend while reading body."),
+                ApiError::InvalidContentLength(_msg) => InvalidContentLength(),
+                ApiError::ContentNotSupported(_msg) => ContentNotSupported(),
+                ApiError::RequestTimedout(_msg) => RequestTimeout(),
+                ApiError::RequestDataNotUTF8(_msg) => UTF8Error(),
+                ApiError::ContentTypeRequired(_msg) => ContentTypeRequired(),
+                ApiError::MethodNotAllowed(_msg, allow) => {
+                    let mut resp = MethodNotAllowed(_msg.unwrap());
+                    resp.add_header("Allow", &allow);
+                    resp
+                }
+                ApiError::MethodNotSupported(_msg) => MethodNotSupported(_msg.unwrap()),
+                ApiError::ResourceNotFound(_msg) => Notfound404(_msg.unwrap()),
+                ApiError::InvalidPath(_msg) => BadRequest(&_msg.unwrap()),
+                ApiError::MalformedBody(_msg) => BadRequest(&_msg.unwrap_or(Cow::Borrowed("Malformed request body."))),
+                ApiError::InvalidHeader(_msg) => BadRequest(&_msg.unwrap_or(Cow::Borrowed("Invalid header."))),
+                ApiError::NotImplemented(_msg) => {
+                    create_response(&_msg.unwrap_or(Cow::Borrowed("Not implemented.")), 501, ContentType::TEXT, false).unwrap()
+                }
+                ApiError::ExpectationFailed(_msg) => {
+                    create_response(&_msg.unwrap_or(Cow::Borrowed("Expectation failed.")), 417, ContentType::TEXT, false).unwrap()
+                }
+                ApiError::URITooLong(_msg) => URITooLong(app_env.max_request_line_len),
+                ApiError::HeaderFieldsTooLarge(_msg) => {
+                    HeaderFieldsTooLarge(&_msg.unwrap_or(Cow::Borrowed("Request header fields too large.")))
+                }
+                ApiError::InsufficientStorage(_msg) => InsufficientStorage(),
+                ApiError::PermanentRedirect(_msg, location) => PermanentRedirect(&location),
+                // A message tells which limit was hit, e.g. one of the multipart limits.
+                ApiError::PayloadTooLarge(_msg) => match _msg {
+                    Some(msg) => create_response(&msg, 413, ContentType::TEXT, false).unwrap(),
+                    None => PayloadTooLarge(app_env.maximum_pay_load_in_bytes / (1024 * 1024)),
+                },
+                ApiError::RequestNotHttp(_msg) => ReaquestNotHttp(_msg.unwrap()),
+                ApiError::CannotWriteDataToDisk(_msg) => InternalServerError("Internal server error."),
+                ApiError::RequestReadError(_msg) => InternalServerError("Error while reading request metadata."),
+                _ => {
+                    let _ = conn.shutdown(std::net::Shutdown::Both);
+                    return;
+                }
+            };
+            send_error(&conn, &app_env, resp);
+            return;
+        }
     };
     let mut client_keep_alive = false;
     #[allow(unused_assignments)]
     let mut server_keep_alive = false;
     if first_req {
         if let Some(keep_alive_val) = req_map.headers.get("Connection") {
-            if keep_alive_val.as_str().to_ascii_lowercase() == "keep-alive" {
+            if keep_alive_val
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("keep-alive"))
+            {
                 client_keep_alive = true;
             }
         } else {
@@ -229,13 +102,51 @@ pub(crate) fn Client(
     if !first_req {
         client_keep_alive = true;
     }
-    let mut path_params = HashMap::new();
-    let route = unsafe { app_env.routes.as_ref() }
-        .get_route(&req_map.resource, Some(&mut path_params))
-        .unwrap();
-
-    let mut resp = (route.function)(&req_map, path_params);
+    let mut path_params = PathParams::new();
+    // parse_metadata took a snapshot of the routes for this request.
+    let routes = req_map.routes.clone().unwrap();
+    // parse_metadata already made sure the method is valid and there is a handler for this request.
+    let method = Method::from_string(&req_map.method).unwrap();
+    let (router, host_params) = routes.for_host(req_map.host().as_deref());
+    let route = router.get_route(&req_map.resource, Some(&mut path_params));
+    let fallback = match route {
+        Some(_) => None,
+        None => router.get_fallback(&req_map.resource, Some(&mut path_params)),
+    };
+    // Parameters of a virtual host pattern come after the path parameters.
+    for (name, val) in host_params.iter() {
+        path_params.insert(name, val);
+    }
+    let mut resp = match route {
+        Some(route) if !route.allows(&method) => {
+            // Only reachable with a user defined 405 handler.
+            let mut resp = routes.method_not_allowed.as_ref().unwrap()(&req_map, path_params);
+            if !resp.headers.contains_key("Allow") {
+                resp.add_header("Allow", &route.allow_header());
+            }
+            resp
+        }
+        Some(route) => match route.handler(&method) {
+            Some(handler) => handler(&req_map, path_params),
+            // HEAD without it's own handler, answered by the GET handler.
+            None if method == Method::HEAD => {
+                route.handler(&Method::GET).unwrap()(&req_map, path_params)
+            }
+            // OPTIONS without it's own handler.
+            None => AllowedMethods(&route.allow_header()),
+        },
+        None => match fallback {
+            Some(fallback) => fallback(&req_map, path_params),
+            // Only reachable with a user defined 404 handler.
+            None => routes.not_found.as_ref().unwrap()(&req_map, path_params),
+        },
+    };
+    // A response to HEAD never has a body, the headers stay the same as for GET.
+    resp.head_only = method == Method::HEAD;
     resp.add_header("Host", &app_env.host);
+    if req_map.body_unread {
+        resp.keep_alive = false;
+    }
 
     if resp.keep_alive && first_req {
         resp.add_header("Connection", "keep-alive");
@@ -255,7 +166,7 @@ pub(crate) fn Client(
         server_keep_alive = false;
         resp.add_header("Connection", "close");
     }
-    let etag_val_recieved = req_map.headers.get("If-None-Match").map(|s| s.clone());
+    let etag_val_recieved = req_map.headers.get("If-None-Match").map(|s| s.to_string());
     match resp.send_response(
         conn.clone(),
         req_map.protocol,
@@ -296,6 +207,8 @@ pub(crate) fn Client(
             } else {
                 log_response_5xx!(req_map.method, req_map.resource, code);
             }
+            // Temporary files of the request are removed now the response is sent, not after the whole connection.
+            drop(req_map.uploads);
             if keep_alive_count_left > 0 && client_keep_alive && server_keep_alive {
                 Client(
                     Arc::clone(&conn),
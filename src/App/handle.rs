@@ -0,0 +1,203 @@
+//! ## Router handle
+//!
+//! Routes live in a **URLRouter** that is never changed in place. Every request takes a snapshot of the current router
+//! (an `Arc`) once it's request line is read and uses it until the response is sent.
+//! A change clones the current router, applies the change to the copy and swaps the pointer, i.e. read-copy-update.
+//! So a request in flight keeps the routes it started with, and a failed change leaves the routes untouched.
+use super::{
+    parse_methods, route_error, route_function, router::URLRouter, sub_router::Router, Route,
+    RouteFunction, RouteInfo,
+};
+use crate::{error, utils::error::ApiError, Extract::Handler};
+use std::{
+    any::type_name,
+    io,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// The current router of an app, shared between the app, it's handles and the workers.
+pub(crate) struct SharedRouter {
+    current: RwLock<Arc<URLRouter>>,
+    /// Held for the whole clone-update-swap, so two concurrent changes can't overwrite each other.
+    /// Readers never wait for it, only for the swap itself.
+    writer: Mutex<()>,
+}
+impl SharedRouter {
+    pub(crate) fn new() -> Self {
+        Self {
+            current: RwLock::new(Arc::new(URLRouter::new())),
+            writer: Mutex::new(()),
+        }
+    }
+    /// Snapshot of the current routes.
+    pub(crate) fn load(&self) -> Arc<URLRouter> {
+        self.current.read().unwrap().clone()
+    }
+    /// Apply `change` to a copy of the current routes and publish the copy if it succeeds.
+    pub(crate) fn update<T, E>(
+        &self,
+        change: impl FnOnce(&mut URLRouter) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let _writer = self.writer.lock().unwrap();
+        let mut router = (*self.load()).clone();
+        let result = change(&mut router)?;
+        *self.current.write().unwrap() = Arc::new(router);
+        Ok(result)
+    }
+}
+/// ## RouterHandle
+/// A cloneable handle to the routes of an app, see `RastAPI::router_handle`.
+/// It can add, remove or replace routes from any thread, also while the server is running.
+///
+/// Every change is atomic. Requests that already started are answered by the routes they started with,
+/// requests after the change see all of it.
+/// A change copies the route table, so it's meant for things like feature flags, not for every request.
+///
+/// ## Example
+/// ```no_run
+/// use rastapi::RastAPI;
+/// use rastapi::Response::create_response;
+/// use rastapi::utils::ContentType;
+/// use std::{thread, time::Duration};
+///
+/// fn main(){
+///     let mut app=RastAPI::new();
+///     let handle=app.router_handle();
+///     thread::spawn(move ||{
+///         thread::sleep(Duration::from_secs(60));
+///         // Turn the beta endpoint on.
+///         handle.register_route("/beta",vec!["GET"],||create_response("Beta",200,ContentType::TEXT,false).unwrap()).unwrap();
+///         thread::sleep(Duration::from_secs(60));
+///         // And off again.
+///         handle.remove_route("/beta").unwrap();
+///     });
+///     app.run("127.0.0.1",5000);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RouterHandle {
+    pub(crate) shared: Arc<SharedRouter>,
+}
+impl RouterHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            shared: Arc::new(SharedRouter::new()),
+        }
+    }
+    /// Register a route, see `RastAPI::register_route`.
+    pub fn register_route<F, Args>(
+        &self,
+        url: &str,
+        methods: Vec<&str>,
+        func: F,
+    ) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        let route = Route::new(route_function(func), type_name::<F>(), parse_methods(methods)?);
+        self.shared
+            .update(|router| router.add_route(url, route))
+            .map_err(route_error)
+    }
+    /// Register a named route, see `RastAPI::register_named_route`.
+    pub fn register_named_route<F, Args>(
+        &self,
+        name: &str,
+        url: &str,
+        methods: Vec<&str>,
+        func: F,
+    ) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        let route = Route::new(route_function(func), type_name::<F>(), parse_methods(methods)?);
+        self.shared
+            .update(|router| router.add_named_route(Some(name), url, route))
+            .map_err(route_error)
+    }
+    /// Remove the route registered with the pattern `url`, with all of it's methods and it's name.
+    /// `url` must be written exactly as it was registered, e.g. `/users/{id:int}`.
+    ///
+    /// Returns an error of kind `NotFound` if no route is registered with this pattern.
+    pub fn remove_route(&self, url: &str) -> Result<(), io::Error> {
+        self.shared.update(|router| match router.remove_route(url) {
+            Some(_route) => Ok(()),
+            None => Err(not_registered(url)),
+        })
+    }
+    /// Replace the route registered with the pattern `url` by a new one in a single step,
+    /// so no request sees the url without a route. The name of the old route is kept.
+    /// If no route is registered with this pattern, the new route is just added.
+    ///
+    /// Can return the same errors as `register_route`.
+    pub fn replace_route<F, Args>(
+        &self,
+        url: &str,
+        methods: Vec<&str>,
+        func: F,
+    ) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        let route = Route::new(route_function(func), type_name::<F>(), parse_methods(methods)?);
+        self.shared
+            .update(|router| {
+                let name = router.name_of(url).map(|n| n.to_string());
+                router.remove_route(url);
+                router.add_named_route(name.as_deref(), url, route)
+            })
+            .map_err(route_error)
+    }
+    /// Mount a router under `prefix`, see `RastAPI::mount`. Either all of it's routes get added or none.
+    pub fn mount(&self, prefix: &str, router: Router) -> Result<(), io::Error> {
+        self.shared
+            .update(|url_router| mount_into(url_router, prefix, router))
+            .map_err(route_error)
+    }
+    /// Serve a router on a virtual host, see `RastAPI::mount_host`. Either all of it's routes get added or none.
+    pub fn mount_host(&self, host: &str, router: Router) -> Result<(), io::Error> {
+        self.shared
+            .update(|url_router| mount_into(url_router.host_mut(host)?, "/", router))
+            .map_err(route_error)
+    }
+    /// List every registered route, see `RastAPI::routes`.
+    pub fn routes(&self) -> impl Iterator<Item = RouteInfo> {
+        self.shared.load().routes().into_iter()
+    }
+    /// Build the url of a named route, see `RastAPI::url_for`.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        self.shared.load().url_for(name, params)
+    }
+    /// Add a fallback for `prefix`.
+    pub(crate) fn add_fallback(&self, prefix: &str, fallback: RouteFunction) -> Result<(), io::Error> {
+        self.shared
+            .update(|router| router.add_fallback(prefix, fallback))
+            .map_err(route_error)
+    }
+    /// Change the router level settings, e.g. the 404 handler.
+    pub(crate) fn configure(&self, change: impl FnOnce(&mut URLRouter)) {
+        let _ = self.shared.update(|router| {
+            change(router);
+            Ok::<(), ()>(())
+        });
+    }
+}
+/// Add the routes and fallbacks of `router` mounted on `prefix`.
+fn mount_into(url_router: &mut URLRouter, prefix: &str, router: Router) -> Result<(), ApiError<'static>> {
+    let (routes, fallbacks) = router.flatten(prefix);
+    for (url, name, route) in routes {
+        url_router.add_named_route(name.as_deref(), &url, route)?;
+    }
+    for (prefix, fallback) in fallbacks {
+        url_router.add_fallback(&prefix, fallback)?;
+    }
+    Ok(())
+}
+fn not_registered(url: &str) -> io::Error {
+    let msg = format!("No route registered with the pattern {}", url);
+    error!("{}", msg);
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
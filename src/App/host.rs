@@ -0,0 +1,118 @@
+//! ## Virtual hosts
+//!
+//! Host patterns select the routes of a request by it's `Host` header. A pattern is a domain name whose labels are either
+//! static, e.g. `api.example.local`, or a parameter that matches exactly one label, e.g. `{tenant}.example.local`.
+//! Host names are compared case-insensitively and without the port.
+use crate::utils::error::ApiError;
+use std::{borrow::Cow, cmp::Ordering};
+
+/// A single label of a host pattern.
+#[derive(Clone, PartialEq, Eq)]
+enum Label {
+    Static(String),
+    Param(String),
+}
+/// A parsed host pattern, e.g. `{tenant}.example.local`.
+#[derive(Clone)]
+pub(crate) struct HostPattern {
+    /// The pattern as it was registered, lowercased.
+    pub(crate) pattern: String,
+    labels: Vec<Label>,
+}
+impl HostPattern {
+    pub(crate) fn parse(host: &str) -> Result<Self, ApiError<'static>> {
+        let pattern = host.trim_end_matches('.').to_ascii_lowercase();
+        let invalid = |msg: &str| {
+            ApiError::InvalidRoutePattern(Some(Cow::Owned(format!("{} in host {}", msg, host))))
+        };
+        if pattern.is_empty() {
+            return Err(invalid("Empty host name"));
+        }
+        let mut labels = Vec::new();
+        for label in pattern.split('.') {
+            if let Some(name) = label.strip_prefix('{').and_then(|l| l.strip_suffix('}')) {
+                if name.is_empty() || name.contains(['{', '}', ':', '*']) {
+                    return Err(invalid("Invalid parameter"));
+                }
+                if labels.contains(&Label::Param(name.to_string())) {
+                    return Err(invalid("Duplicate parameter"));
+                }
+                labels.push(Label::Param(name.to_string()));
+            } else if label.is_empty()
+                || !label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+            {
+                return Err(invalid("Invalid label"));
+            } else {
+                labels.push(Label::Static(label.to_string()));
+            }
+        }
+        Ok(Self { pattern, labels })
+    }
+    /// Number of parameter labels.
+    fn param_count(&self) -> usize {
+        self.labels
+            .iter()
+            .filter(|l| matches!(l, Label::Param(_)))
+            .count()
+    }
+    /// Order in which patterns are tried, so a host matched by more than one pattern always picks the same one.
+    /// Patterns with fewer parameters, i.e. more static labels, come first. Between patterns with as many parameters
+    /// the one with a static label at the first position where they differ comes first, like static segments win in routes.
+    /// Patterns of the same shape are equal.
+    pub(crate) fn precedence(&self, other: &HostPattern) -> Ordering {
+        self.param_count().cmp(&other.param_count()).then_with(|| {
+            self.labels
+                .iter()
+                .zip(other.labels.iter())
+                .map(|pair| match pair {
+                    (Label::Static(_), Label::Param(_)) => Ordering::Less,
+                    (Label::Param(_), Label::Static(_)) => Ordering::Greater,
+                    _ => Ordering::Equal,
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+    /// Check if both patterns match the same hosts, i.e. they only differ by the names of their parameters.
+    pub(crate) fn same_shape(&self, other: &HostPattern) -> bool {
+        self.labels.len() == other.labels.len()
+            && self.labels.iter().zip(other.labels.iter()).all(|pair| match pair {
+                (Label::Static(a), Label::Static(b)) => a == b,
+                (Label::Param(_), Label::Param(_)) => true,
+                _ => false,
+            })
+    }
+    /// Match a host name, without the port. Returns the captured parameters.
+    pub(crate) fn matches(&self, host: &str) -> Option<Vec<(String, String)>> {
+        let host = host.trim_end_matches('.');
+        let mut params = Vec::new();
+        let mut host_labels = host.split('.');
+        for label in self.labels.iter() {
+            let host_label = host_labels.next().filter(|l| !l.is_empty())?;
+            match label {
+                Label::Static(s) if s.eq_ignore_ascii_case(host_label) => (),
+                Label::Static(_) => return None,
+                Label::Param(name) => params.push((name.clone(), host_label.to_ascii_lowercase())),
+            }
+        }
+        if host_labels.next().is_some() {
+            return None;
+        }
+        Some(params)
+    }
+}
+/// Host name of a `Host` header value, i.e. without the port. e.g. `api.example.local:5000` gives `api.example.local`.
+pub(crate) fn host_name(header: &str) -> &str {
+    let header = header.trim();
+    if header.starts_with('[') {
+        // IPv6 literal, e.g. [::1]:5000
+        return match header.find(']') {
+            Some(end) => &header[..=end],
+            None => header,
+        };
+    }
+    match header.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => header,
+    }
+}
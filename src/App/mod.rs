@@ -1,98 +1,195 @@
 #![allow(dead_code)]
 pub(crate) mod client;
-use crate::{cache::Cache, utils, RastAPI, Request, Response};
-use regex;
-use regex::Regex;
-use std::{collections::HashMap, path::PathBuf, ptr::NonNull, time::Duration};
-use utils::Method;
-use Request::HttpRequest;
+pub(crate) mod handle;
+pub(crate) mod host;
+pub(crate) mod router;
+pub(crate) mod sub_router;
+use crate::{cache::Cache, error, utils, Extract::Handler, RastAPI, Request, Response};
+use handle::SharedRouter;
+pub(crate) use router::URLRouter;
+pub use handle::RouterHandle;
+pub use sub_router::{Next, Router};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    ptr::NonNull,
+    sync::Arc,
+    time::Duration,
+};
+use utils::{error::ApiError, Method, TrailingSlash};
+use Request::{DiskQuota, HttpRequest, PathParams};
 use Response::HttpResponse;
-/// Signature of route handlers. i.e. functions that get called on every request.
-pub(crate) type RouteFunction = fn(&HttpRequest, HashMap<String, String>) -> HttpResponse;
-/// Structure to store router function and allowed methods on that route.
-#[derive(Debug)]
+/// Signature of route handlers. i.e. functions or closures that get called on every request.
+/// Handlers are shared between worker threads and between the methods they are registered for, so they live behind an `Arc`.
+pub(crate) type RouteFunction = Arc<dyn Fn(&HttpRequest, PathParams) -> HttpResponse + Send + Sync>;
+/// Structure to store the route handlers of a url, one per allowed method.
+#[derive(Clone)]
 pub(crate) struct Route {
-    pub(crate) function: RouteFunction,
-    pub(crate) methods: Vec<Method>,
+    /// Handlers of this route in registration order, with the type name of the function or closure they were made from.
+    pub(crate) handlers: Vec<(Method, RouteFunction, &'static str)>,
+    /// The pattern this route was registered with, e.g. `/users/{id}`.
+    pub(crate) pattern: String,
 }
 impl Route {
-    pub(crate) fn new(func: RouteFunction, mut methods: Vec<Method>) -> Self {
+    pub(crate) fn new(func: RouteFunction, handler_name: &'static str, mut methods: Vec<Method>) -> Self {
         if methods.is_empty() {
             methods.push(Method::GET);
         }
+        let mut handlers: Vec<(Method, RouteFunction, &'static str)> = Vec::with_capacity(methods.len());
+        for method in methods {
+            if !handlers.iter().any(|(m, _, _)| *m == method) {
+                handlers.push((method, func.clone(), handler_name));
+            }
+        }
         Self {
-            function: func,
-            methods: methods,
+            handlers,
+            pattern: String::new(),
         }
     }
-}
-/// Main router of our REST API. It maps urls to their corresponding handlers.
-pub(crate) struct URLRouter {
-    pub(crate) router: HashMap<String, Route>,
-}
-
-impl URLRouter {
-    pub(crate) fn new() -> Self {
-        Self {
-            router: HashMap::new(),
+    /// Get the handler registered for `method`.
+    pub(crate) fn handler(&self, method: &Method) -> Option<&RouteFunction> {
+        self.handlers
+            .iter()
+            .find(|(m, _, _)| m == method)
+            .map(|(_, func, _)| func)
+    }
+    /// Check if a request with `method` can be answered by this route.
+    /// HEAD is answered by the GET handler and OPTIONS automatically, unless they have their own handlers.
+    pub(crate) fn allows(&self, method: &Method) -> bool {
+        match method {
+            Method::HEAD => self.handler(&Method::HEAD).is_some() || self.handler(&Method::GET).is_some(),
+            Method::OPTIONS => true,
+            _ => self.handler(method).is_some(),
+        }
+    }
+    /// Methods allowed on this route, formatted as the value of an `Allow` header. e.g. `GET, POST, HEAD, OPTIONS`
+    pub(crate) fn allow_header(&self) -> String {
+        let mut methods: Vec<&str> = self.handlers.iter().map(|(m, _, _)| m.as_str()).collect();
+        for (implicit, name) in [(Method::HEAD, "HEAD"), (Method::OPTIONS, "OPTIONS")] {
+            if self.handler(&implicit).is_none() && self.allows(&implicit) {
+                methods.push(name);
+            }
         }
+        methods.join(", ")
     }
-    /// Get a route for a specific URL. It also determines the path parameters.
-    pub(crate) fn get_route(
-        &self,
-        url: &str,
-        path_params_opt: Option<&mut HashMap<String, String>>,
-    ) -> Option<&Route> {
-        for (key, val) in self.router.iter() {
-            let re = Regex::new(key).unwrap();
-            if re.is_match(url) {
-                if let Some(path_params) = path_params_opt {
-                    if let Some(captures) = re.captures(url) {
-                        for name in re.capture_names().flatten() {
-                            if let Some(val) = captures.name(name) {
-                                path_params.insert(name.to_string(), val.as_str().to_string());
-                            }
-                        }
-                    }
-                }
-
-                return Some(val);
+    /// Merge the handlers of `other` into this route.
+    /// Fails without changing anything if a method is registered on both.
+    pub(crate) fn merge(&mut self, other: Route) -> Result<(), Method> {
+        for (method, _, _) in other.handlers.iter() {
+            if self.handler(method).is_some() {
+                return Err(method.clone());
             }
         }
-        None
+        self.handlers.extend(other.handlers);
+        Ok(())
     }
-    /// Register a route.
-    pub(crate) fn add_route(&mut self, url: &str, route: Route) -> () {
-        let re = Regex::new(r#"\{([a-zA-Z0-9_]+)\}"#).unwrap();
-        let regex_pattern = re.replace_all(url, "(?P<$1>[ -~]+)").to_string();
-        let mut final_pattern = String::from("^");
-        final_pattern.push_str(&regex_pattern);
-        final_pattern.push('$');
-        let _res = self.router.insert(final_pattern, route);
+    /// Describe this route for `RastAPI::routes`.
+    pub(crate) fn info(&self, name: Option<&str>) -> RouteInfo {
+        RouteInfo {
+            pattern: self.pattern.clone(),
+            name: name.map(|n| n.to_string()),
+            handlers: self
+                .handlers
+                .iter()
+                .map(|(method, _, handler)| HandlerInfo {
+                    method: method.as_str().to_string(),
+                    handler,
+                })
+                .collect(),
+            allow: self.allow_header(),
+            host: None,
+        }
     }
-    /// Remove a already registered route.
-    pub(crate) fn remove_route(&mut self, url: &str) -> () {
-        let re = Regex::new(r#"\{([a-zA-Z0-9_]+)\}"#).unwrap();
-        let regex_pattern = re.replace_all(url, "(?P<$1>[ -~]+)").to_string();
-        let mut final_pattern = String::from("^");
-        final_pattern.push_str(&regex_pattern);
-        final_pattern.push('$');
-        self.router.remove(&final_pattern);
+}
+/// ## RouteInfo
+/// A registered route, as listed by `RastAPI::routes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    /// The pattern the route was registered with, including the prefix it was mounted on. e.g. `/api/v1/users/{id:int}`
+    pub pattern: String,
+    /// Name of the route if it was registered with `register_named_route`.
+    pub name: Option<String>,
+    /// Handlers of the route in registration order, one per method.
+    pub handlers: Vec<HandlerInfo>,
+    /// Every method the route answers, including the implicit HEAD and OPTIONS. Same as it's `Allow` header.
+    pub allow: String,
+    /// Host pattern of the virtual host the route belongs to, see `RastAPI::mount_host`. None for the default host.
+    pub host: Option<String>,
+}
+impl RouteInfo {
+    /// Methods the route was registered with, e.g. `["GET", "POST"]`.
+    pub fn methods(&self) -> Vec<&str> {
+        self.handlers.iter().map(|h| h.method.as_str()).collect()
     }
 }
+/// A method of a route and the handler registered for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerInfo {
+    pub method: String,
+    /// Type name of the handler function or closure, e.g. `my_app::users::user_detail`.
+    /// Handlers of mounted routers keep the name of the function they were registered with.
+    pub handler: &'static str,
+}
+/// Turn any handler into a **RouteFunction**.
+pub(crate) fn route_function<F, Args>(func: F) -> RouteFunction
+where
+    F: Handler<Args>,
+    Args: 'static,
+{
+    Arc::new(move |req: &HttpRequest, path_params: PathParams| func.call(req, path_params))
+}
+/// Parse the methods passed while registering a route.
+pub(crate) fn parse_methods(methods: Vec<&str>) -> Result<Vec<Method>, io::Error> {
+    let mut method_list: Vec<Method> = Vec::new();
+    for m in methods {
+        if let Some(_m_) = Method::from_string(m) {
+            method_list.push(_m_);
+        } else {
+            error!(
+                "No http method named {}. Try using only uppercase letters like GET,POST",
+                m
+            );
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+    }
+    Ok(method_list)
+}
+/// Log a routing error and convert it to the `io::Error` returned by the public API.
+pub(crate) fn route_error(e: ApiError) -> io::Error {
+    error!("{}", e);
+    let kind = match e {
+        ApiError::RouteConflict(_) => io::ErrorKind::AlreadyExists,
+        _ => io::ErrorKind::InvalidInput,
+    };
+    io::Error::new(kind, e.to_string())
+}
+/// Shared application state, one value per type. See `RastAPI::with_state`.
+pub(crate) type AppState = HashMap<TypeId, Arc<dyn Any + Send + Sync>>;
 /// Some data related to the current app and other configurations which gets passed to the job handlers for every request.
 pub(crate) struct AppEnv {
     pub host: String,
     pub port: u16,
     pub cache: NonNull<Cache<PathBuf>>,
-    pub routes: NonNull<URLRouter>,
+    pub routes: Arc<SharedRouter>,
     pub maximum_pay_load_in_bytes: usize,
     pub read_time_out: Duration,
     pub write_time_out: Duration,
     pub keep_alive_time_out: Duration,
     pub keep_alive_max_count: u8,
     pub incoming_file_directory: String,
-    pub send_buffer_size:usize
+    /// Size of the temporary files in the incoming files directory, shared by every request.
+    pub incoming_quota: Arc<DiskQuota>,
+    pub in_memory_body_limit: u64,
+    pub max_request_line_len: usize,
+    pub max_header_bytes: usize,
+    pub max_header_count: usize,
+    pub multipart_max_part_size: u64,
+    pub multipart_max_total_size: u64,
+    pub send_buffer_size:usize,
+    pub state: AppState,
+    pub trailing_slash: TrailingSlash,
 }
 impl AppEnv {
     pub fn new(host: &str, port: u16, app: &RastAPI,send_buf_size:usize) -> Self {
@@ -100,14 +197,25 @@ impl AppEnv {
             host: host.to_string(),
             port,
             cache: app.cache,
-            routes: app.routes,
+            routes: app.routes.shared.clone(),
             maximum_pay_load_in_bytes: app.payload_maximum_size_in_MB * 1024 * 1024,
             read_time_out: app.read_time_out,
             write_time_out: app.write_time_out,
             keep_alive_time_out: app.keep_alive_time_out,
             keep_alive_max_count: app.keep_alive_max_count,
             incoming_file_directory: app.file_upload_directory_name.clone(),
-            send_buffer_size:send_buf_size
+            incoming_quota: Arc::new(DiskQuota::new(
+                app.incoming_files_quota_in_MB.map(|size| size as u64 * 1024 * 1024),
+            )),
+            in_memory_body_limit: app.in_memory_body_limit_in_KB as u64 * 1024,
+            max_request_line_len: app.max_request_line_len,
+            max_header_bytes: app.max_header_bytes,
+            max_header_count: app.max_header_count,
+            multipart_max_part_size: app.multipart_max_part_size_in_MB as u64 * 1024 * 1024,
+            multipart_max_total_size: app.multipart_max_total_size_in_MB as u64 * 1024 * 1024,
+            send_buffer_size:send_buf_size,
+            state: app.state.clone(),
+            trailing_slash: app.trailing_slash,
         }
     }
 }
@@ -0,0 +1,872 @@
+//! ## Router
+//!
+//! Segment based trie router. Route patterns are split on `/` and every segment becomes a node in the trie
+//! when the route is registered, so a lookup only walks the segments of the requested url.
+//!
+//! A segment of a pattern can be
+//!  - a static segment, e.g. `users`.
+//!  - a path parameter, e.g. `{id}`. It matches exactly one non-empty segment. A converter can be added after a `:`
+//!    to constrain the values it accepts, e.g. `{id:int}`, `{id:uuid}`, `{price:float}` or a regex like `{slug:[a-z-]+}`.
+//!  - a wildcard, e.g. `{*rest}` or `{rest:path}`. It matches the rest of the url (one or more segments) and must be the last segment.
+//!
+//! When more than one pattern can match a url, static segments win over parameters and parameters win over wildcards.
+//! Among parameters at the same position, the ones with a converter are tried before plain `{name}` parameters.
+//! The decision is taken segment by segment, left to right, and we backtrack if a more specific branch doesn't lead to a route,
+//! so a value rejected by a converter falls through to the next candidate.
+use super::{
+    host::{host_name, HostPattern},
+    Route, RouteFunction, RouteInfo,
+};
+use crate::{
+    utils::{error::ApiError, percent_encode, unescape_segment},
+    Request::PathParams,
+};
+use regex::Regex;
+use std::{borrow::Cow, collections::HashMap};
+
+/// Converter of a path parameter. It decides which values a parameter accepts.
+#[derive(Clone)]
+enum Converter {
+    /// Any non-empty segment. `{name}` or `{name:str}`.
+    Str,
+    /// Optionally signed integer. `{name:int}`.
+    Int,
+    /// Floating point number. `{name:float}`.
+    Float,
+    /// UUID in it's hyphenated form. `{name:uuid}`.
+    Uuid,
+    /// A regex that must match the whole segment, e.g. `{slug:[a-z-]+}`.
+    Regex(Regex),
+}
+impl Converter {
+    fn from_spec(spec: &str, url: &str) -> Result<Self, ApiError<'static>> {
+        let conv = match spec {
+            "" => Self::Str,
+            "int" => Self::Int,
+            "float" => Self::Float,
+            "uuid" => Self::Uuid,
+            _ => match Regex::new(&format!("^(?:{})$", spec)) {
+                Ok(re) => Self::Regex(re),
+                Err(e) => {
+                    return Err(ApiError::InvalidRoutePattern(Some(Cow::Owned(format!(
+                        "Invalid regex {} in route {}\n{}",
+                        spec, url, e
+                    )))));
+                }
+            },
+        };
+        Ok(conv)
+    }
+    fn accepts(&self, segment: &str) -> bool {
+        match self {
+            Self::Str => true,
+            Self::Int => {
+                let digits = segment.strip_prefix('-').unwrap_or(segment);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            }
+            Self::Float => segment.parse::<f64>().map(|f| f.is_finite()).unwrap_or(false),
+            Self::Uuid => {
+                segment.len() == 36
+                    && segment.bytes().enumerate().all(|(i, b)| match i {
+                        8 | 13 | 18 | 23 => b == b'-',
+                        _ => b.is_ascii_hexdigit(),
+                    })
+            }
+            Self::Regex(re) => re.is_match(segment),
+        }
+    }
+    /// Check if a segment may be accepted by both converters. Only a uuid tells apart from a number for sure,
+    /// a regex may accept anything.
+    fn may_overlap(&self, other: &Converter) -> bool {
+        !matches!(
+            (self, other),
+            (Self::Uuid, Self::Int | Self::Float) | (Self::Int | Self::Float, Self::Uuid)
+        )
+    }
+}
+/// A single segment of a route pattern.
+enum Segment<'a> {
+    Static(&'a str),
+    /// Name and converter spec of a path parameter.
+    Param(&'a str, &'a str),
+    Wildcard(&'a str),
+}
+/// Split a url on `/`, ignoring the ones inside `{}` so converter regexes can contain them.
+fn split_segments(path: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+    for (idx, c) in path.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            '/' if depth == 0 => {
+                segments.push(&path[start..idx]);
+                start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    segments.push(&path[start..]);
+    segments
+}
+/// Split a route pattern into segments and validate them.
+fn parse_pattern(url: &str) -> Result<Vec<Segment<'_>>, ApiError<'static>> {
+    let path = url.strip_prefix('/').unwrap_or(url);
+    let mut segments = Vec::new();
+    if path.is_empty() {
+        return Ok(segments);
+    }
+    let raw_segments = split_segments(path);
+    for (idx, raw) in raw_segments.iter().enumerate() {
+        if let Some(inner) = raw.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            let (name, spec) = match inner.split_once(':') {
+                Some((name, "str")) => (name, ""),
+                Some((name, spec)) => (name, spec),
+                None => (inner, ""),
+            };
+            let (is_wildcard, name) = match name.strip_prefix('*') {
+                Some(n) if spec.is_empty() => (true, n),
+                Some(_) => {
+                    return Err(ApiError::InvalidRoutePattern(Some(Cow::Owned(format!(
+                        "A wildcard can't have a converter in route {}",
+                        url
+                    )))));
+                }
+                None => (spec == "path", name),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(ApiError::InvalidRoutePattern(Some(Cow::Owned(format!(
+                    "Invalid parameter name in route {}",
+                    url
+                )))));
+            }
+            if is_wildcard {
+                if idx != raw_segments.len() - 1 {
+                    return Err(ApiError::InvalidRoutePattern(Some(Cow::Owned(format!(
+                        "Wildcard must be the last segment of route {}",
+                        url
+                    )))));
+                }
+                segments.push(Segment::Wildcard(name));
+            } else {
+                segments.push(Segment::Param(name, spec));
+            }
+        } else if raw.contains('{') || raw.contains('}') {
+            return Err(ApiError::InvalidRoutePattern(Some(Cow::Owned(format!(
+                "A path parameter must take a whole segment in route {}",
+                url
+            )))));
+        } else {
+            segments.push(Segment::Static(raw));
+        }
+    }
+    Ok(segments)
+}
+/// Check that `url` is a valid route pattern without registering it.
+pub(crate) fn validate_pattern(url: &str) -> Result<(), ApiError<'static>> {
+    for segment in parse_pattern(url)? {
+        if let Segment::Param(_name, spec) = segment {
+            Converter::from_spec(spec, url)?;
+        }
+    }
+    Ok(())
+}
+/// A parameter or wildcard edge of the trie.
+#[derive(Clone)]
+struct ParamChild {
+    name: String,
+    /// Converter spec as written in the pattern. Used to detect ambiguous routes.
+    spec: String,
+    converter: Converter,
+    node: Node,
+}
+/// Node of the routing trie.
+#[derive(Default, Clone)]
+struct Node {
+    /// Children reached by an exact segment.
+    static_children: HashMap<String, Node>,
+    /// Children reached by a single segment accepted by their converter. Constrained ones come first.
+    param_children: Vec<ParamChild>,
+    /// Child that swallows the rest of the url.
+    wildcard_child: Option<Box<ParamChild>>,
+    /// Route that ends at this node.
+    route: Option<Route>,
+    /// Handler for unmatched urls under this node. Set when a **Router** with a fallback is mounted here.
+    fallback: Option<RouteFunction>,
+}
+impl Node {
+    /// Find the route for `path`, i.e. the part of the url that's left after the current node.
+    /// `path` is None when there are no segments left.
+    fn find<'n, 'u>(
+        &'n self,
+        path: Option<&'u str>,
+        captures: &mut Vec<(&'n str, &'u str)>,
+    ) -> Option<&'n Route> {
+        let path = match path {
+            Some(p) => p,
+            None => {
+                return self.route.as_ref();
+            }
+        };
+        let (segment, rest) = match path.split_once('/') {
+            Some((seg, rest)) => (seg, Some(rest)),
+            None => (path, None),
+        };
+        let decoded = unescape_segment(segment);
+        if let Some(child) = self.static_children.get(decoded.as_ref()) {
+            if let Some(route) = child.find(rest, captures) {
+                return Some(route);
+            }
+        }
+        if !segment.is_empty() {
+            for child in self.param_children.iter() {
+                if !child.converter.accepts(&decoded) {
+                    continue;
+                }
+                captures.push((child.name.as_str(), segment));
+                if let Some(route) = child.node.find(rest, captures) {
+                    return Some(route);
+                }
+                captures.pop();
+            }
+        }
+        if let Some(child) = &self.wildcard_child {
+            if let Some(route) = &child.node.route {
+                captures.push((child.name.as_str(), path));
+                return Some(route);
+            }
+        }
+        None
+    }
+    /// Find the deepest fallback along `path`, following the same precedence as `find`.
+    fn find_fallback<'n, 'u>(
+        &'n self,
+        path: Option<&'u str>,
+        captures: &mut Vec<(&'n str, &'u str)>,
+    ) -> Option<&'n RouteFunction> {
+        if let Some(path) = path {
+            let (segment, rest) = match path.split_once('/') {
+                Some((seg, rest)) => (seg, Some(rest)),
+                None => (path, None),
+            };
+            let decoded = unescape_segment(segment);
+            if let Some(child) = self.static_children.get(decoded.as_ref()) {
+                if let Some(fallback) = child.find_fallback(rest, captures) {
+                    return Some(fallback);
+                }
+            }
+            if !segment.is_empty() {
+                for child in self.param_children.iter() {
+                    if !child.converter.accepts(&decoded) {
+                        continue;
+                    }
+                    captures.push((child.name.as_str(), segment));
+                    if let Some(fallback) = child.node.find_fallback(rest, captures) {
+                        return Some(fallback);
+                    }
+                    captures.pop();
+                }
+            }
+        }
+        self.fallback.as_ref()
+    }
+    /// Collect every route under this node.
+    fn collect<'n>(&'n self, routes: &mut Vec<&'n Route>) {
+        if let Some(route) = &self.route {
+            routes.push(route);
+        }
+        for child in self.static_children.values() {
+            child.collect(routes);
+        }
+        for child in self.param_children.iter() {
+            child.node.collect(routes);
+        }
+        if let Some(child) = &self.wildcard_child {
+            child.node.collect(routes);
+        }
+    }
+    /// Walk down the trie along `segments`, creating the missing nodes.
+    fn walk_or_insert(&mut self, segments: &[Segment], url: &str) -> Result<&mut Node, ApiError<'static>> {
+        let mut node = self;
+        for segment in segments {
+            node = match segment {
+                Segment::Static(s) => node.static_children.entry(s.to_string()).or_default(),
+                Segment::Param(name, spec) => node.param_child(name, spec, url)?,
+                Segment::Wildcard(name) => node.wildcard_child(name, url)?,
+            };
+        }
+        Ok(node)
+    }
+    /// Walk down the trie along `segments` without creating nodes.
+    fn walk_mut(&mut self, segments: &[Segment]) -> Option<&mut Node> {
+        let mut node = self;
+        for segment in segments {
+            node = match segment {
+                Segment::Static(s) => node.static_children.get_mut(*s)?,
+                Segment::Param(name, spec) => {
+                    let child = node
+                        .param_children
+                        .iter_mut()
+                        .find(|c| c.name == *name && c.spec == *spec)?;
+                    &mut child.node
+                }
+                Segment::Wildcard(name) => match &mut node.wildcard_child {
+                    Some(child) if child.name == *name => &mut child.node,
+                    _ => return None,
+                },
+            };
+        }
+        Some(node)
+    }
+    /// Get the child for a path parameter, creating it if needed.
+    /// Two different names with the same converter at the same position make the routes ambiguous, so do two constrained
+    /// parameters whose converters may accept the same segment, e.g. `{id:int}` and `{price:float}`.
+    fn param_child(&mut self, name: &str, spec: &str, url: &str) -> Result<&mut Node, ApiError<'static>> {
+        let idx = match self.param_children.iter().position(|c| c.spec == spec) {
+            Some(idx) => {
+                let child = &self.param_children[idx];
+                if child.name != name {
+                    return Err(ambiguous(url, name, &child.name));
+                }
+                idx
+            }
+            None => {
+                let converter = Converter::from_spec(spec, url)?;
+                if !matches!(converter, Converter::Str) {
+                    let overlapping = self
+                        .param_children
+                        .iter()
+                        .find(|c| !matches!(c.converter, Converter::Str) && c.converter.may_overlap(&converter));
+                    if let Some(child) = overlapping {
+                        return Err(ApiError::RouteConflict(Some(Cow::Owned(format!(
+                            "Route {} is ambiguous : parameter {{{}:{}}} may match the same segments as {{{}:{}}} registered at the same position by another route.",
+                            url, name, spec, child.name, child.spec
+                        )))));
+                    }
+                }
+                // Plain parameters go last, after the constrained one if any.
+                let idx = match converter {
+                    Converter::Str => self.param_children.len(),
+                    _ => self
+                        .param_children
+                        .iter()
+                        .position(|c| matches!(c.converter, Converter::Str))
+                        .unwrap_or(self.param_children.len()),
+                };
+                self.param_children.insert(
+                    idx,
+                    ParamChild {
+                        name: name.to_string(),
+                        spec: spec.to_string(),
+                        converter,
+                        node: Node::default(),
+                    },
+                );
+                idx
+            }
+        };
+        Ok(&mut self.param_children[idx].node)
+    }
+    /// Get the wildcard child, creating it if needed.
+    fn wildcard_child(&mut self, name: &str, url: &str) -> Result<&mut Node, ApiError<'static>> {
+        let child = self.wildcard_child.get_or_insert_with(|| {
+            Box::new(ParamChild {
+                name: name.to_string(),
+                spec: String::from("path"),
+                converter: Converter::Str,
+                node: Node::default(),
+            })
+        });
+        if child.name != name {
+            return Err(ambiguous(url, name, &child.name));
+        }
+        Ok(&mut child.node)
+    }
+}
+fn ambiguous(url: &str, name: &str, existing: &str) -> ApiError<'static> {
+    ApiError::RouteConflict(Some(Cow::Owned(format!(
+        "Route {} is ambiguous : parameter {{{}}} is registered as {{{}}} at the same position by another route.",
+        url, name, existing
+    ))))
+}
+/// Main router of our REST API. It maps urls to their corresponding handlers.
+/// It's cloned for every change made while the server is running, see **RouterHandle**.
+#[derive(Clone)]
+pub(crate) struct URLRouter {
+    root: Node,
+    /// Patterns of named routes, used to build urls with `url_for`.
+    names: HashMap<String, String>,
+    /// Handler for urls that match neither a route nor a fallback. If None a plain text 404 is sent.
+    pub(crate) not_found: Option<RouteFunction>,
+    /// Handler for requests with a method the matched route doesn't allow. If None a plain text 405 is sent.
+    pub(crate) method_not_allowed: Option<RouteFunction>,
+    /// Routers of virtual hosts in the order they are tried, see `HostPattern::precedence`.
+    /// Requests for any other host are routed by this router, i.e. the default host.
+    hosts: Vec<(HostPattern, URLRouter)>,
+}
+
+impl URLRouter {
+    pub(crate) fn new() -> Self {
+        Self {
+            root: Node::default(),
+            names: HashMap::new(),
+            not_found: None,
+            method_not_allowed: None,
+            hosts: Vec::new(),
+        }
+    }
+    /// Get a route for a specific URL. It also determines the path parameters.
+    pub(crate) fn get_route(
+        &self,
+        url: &str,
+        path_params_opt: Option<&mut PathParams>,
+    ) -> Option<&Route> {
+        let path = url.strip_prefix('/')?;
+        let path = if path.is_empty() { None } else { Some(path) };
+        let mut captures = Vec::new();
+        let route = self.root.find(path, &mut captures)?;
+        if let Some(path_params) = path_params_opt {
+            for (name, val) in captures {
+                path_params.insert(name, &unescape_segment(val));
+            }
+        }
+        Some(route)
+    }
+    /// Register a route. If the same pattern is already registered, the handlers of both get merged.
+    /// Returns an error if the pattern is malformed, if a method is already registered for this pattern,
+    /// or if it is ambiguous against an already registered route.
+    pub(crate) fn add_route(&mut self, url: &str, route: Route) -> Result<(), ApiError<'static>> {
+        self.add_named_route(None, url, route)
+    }
+    /// Register a route like `add_route`, optionally under a name to build it's url later with `url_for`.
+    /// Returns an error if the name is already taken.
+    pub(crate) fn add_named_route(
+        &mut self,
+        name: Option<&str>,
+        url: &str,
+        mut route: Route,
+    ) -> Result<(), ApiError<'static>> {
+        if let Some(name) = name {
+            if let Some(pattern) = self.names.get(name) {
+                return Err(ApiError::RouteConflict(Some(Cow::Owned(format!(
+                    "Route name {} of route {} is already used by route {}",
+                    name, url, pattern
+                )))));
+            }
+        }
+        let segments = parse_pattern(url)?;
+        let node = self.root.walk_or_insert(&segments, url)?;
+        match &mut node.route {
+            Some(existing) => {
+                // Same url registered again, e.g. once for GET and once for POST.
+                if let Err(method) = existing.merge(route) {
+                    return Err(ApiError::RouteConflict(Some(Cow::Owned(format!(
+                        "Route {} conflicts with already registered route {} : method {} is registered on both.",
+                        url,
+                        existing.pattern,
+                        method.as_str()
+                    )))));
+                }
+            }
+            None => {
+                route.pattern = url.to_string();
+                node.route = Some(route);
+            }
+        }
+        if let Some(name) = name {
+            self.names.insert(name.to_string(), url.to_string());
+        }
+        Ok(())
+    }
+    /// Build the url of the route named `name`, filling it's path parameters from `params`.
+    /// Values get percent-encoded, params that aren't in the pattern are added as query parameters.
+    /// Returns None if there is no such route or a path parameter is missing.
+    pub(crate) fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let pattern = self.names.get(name)?;
+        let segments = parse_pattern(pattern).ok()?;
+        let mut url = String::new();
+        let mut used = Vec::new();
+        for segment in segments.iter() {
+            url.push('/');
+            match segment {
+                Segment::Static(s) => url.push_str(s),
+                Segment::Param(param, _) | Segment::Wildcard(param) => {
+                    let (key, val) = params.iter().find(|(k, _)| k == param)?;
+                    used.push(*key);
+                    // A wildcard spans several segments, so it keeps it's slashes.
+                    let keep_slash = matches!(segment, Segment::Wildcard(_));
+                    url.push_str(&percent_encode(val, keep_slash));
+                }
+            }
+        }
+        if url.is_empty() {
+            url.push('/');
+        }
+        let query = params
+            .iter()
+            .filter(|(k, _)| !used.contains(k))
+            .map(|(k, v)| format!("{}={}", percent_encode(k, false), percent_encode(v, false)))
+            .collect::<Vec<_>>();
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        Some(url)
+    }
+    /// Get the fallback for a url no route matched, i.e. the one set on the longest matching prefix.
+    /// It also determines the path parameters of the prefix.
+    pub(crate) fn get_fallback(
+        &self,
+        url: &str,
+        path_params_opt: Option<&mut PathParams>,
+    ) -> Option<&RouteFunction> {
+        let path = url.strip_prefix('/')?;
+        let path = if path.is_empty() { None } else { Some(path) };
+        let mut captures = Vec::new();
+        let fallback = self.root.find_fallback(path, &mut captures)?;
+        if let Some(path_params) = path_params_opt {
+            for (name, val) in captures {
+                path_params.insert(name, &unescape_segment(val));
+            }
+        }
+        Some(fallback)
+    }
+    /// Set the fallback for every url under `prefix` that doesn't match a route.
+    /// Returns an error if the prefix is malformed or already has a fallback.
+    pub(crate) fn add_fallback(&mut self, prefix: &str, fallback: RouteFunction) -> Result<(), ApiError<'static>> {
+        let segments = parse_pattern(prefix)?;
+        if matches!(segments.last(), Some(Segment::Wildcard(_))) {
+            return Err(ApiError::InvalidRoutePattern(Some(Cow::Owned(format!(
+                "A fallback can't be set on wildcard prefix {}",
+                prefix
+            )))));
+        }
+        let node = self.root.walk_or_insert(&segments, prefix)?;
+        if node.fallback.is_some() {
+            return Err(ApiError::RouteConflict(Some(Cow::Owned(format!(
+                "A fallback is already registered for prefix {}",
+                prefix
+            )))));
+        }
+        node.fallback = Some(fallback);
+        Ok(())
+    }
+    /// Name of the route registered with the pattern `url`.
+    pub(crate) fn name_of(&self, url: &str) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_name, pattern)| *pattern == url)
+            .map(|(name, _pattern)| name.as_str())
+    }
+    /// Describe every registered route, the ones of the default host first, sorted by host and pattern.
+    pub(crate) fn routes(&self) -> Vec<RouteInfo> {
+        let mut routes = Vec::new();
+        self.root.collect(&mut routes);
+        let mut infos: Vec<RouteInfo> = routes
+            .into_iter()
+            .map(|route| route.info(self.name_of(&route.pattern)))
+            .collect();
+        for (host, router) in self.hosts.iter() {
+            infos.extend(router.routes().into_iter().map(|mut info| {
+                info.host = Some(host.pattern.clone());
+                info
+            }));
+        }
+        infos.sort_by(|a, b| (&a.host, &a.pattern).cmp(&(&b.host, &b.pattern)));
+        infos
+    }
+    /// Router for a request to `host`, i.e. the value of it's `Host` header, and the parameters captured from the host name.
+    /// Falls back to this router if no virtual host matches.
+    pub(crate) fn for_host(&self, host: Option<&str>) -> (&URLRouter, Vec<(String, String)>) {
+        if let Some(host) = host.map(host_name) {
+            for (pattern, router) in self.hosts.iter() {
+                if let Some(params) = pattern.matches(host) {
+                    return (router, params);
+                }
+            }
+        }
+        (self, Vec::new())
+    }
+    /// Router of the virtual host `host`, created if needed.
+    /// Returns an error if the pattern is malformed, or if it matches the same hosts as another pattern.
+    pub(crate) fn host_mut(&mut self, host: &str) -> Result<&mut URLRouter, ApiError<'static>> {
+        let pattern = HostPattern::parse(host)?;
+        let idx = match self.hosts.iter().position(|(p, _)| p.same_shape(&pattern)) {
+            Some(idx) if self.hosts[idx].0.pattern == pattern.pattern => idx,
+            Some(idx) => {
+                return Err(ApiError::RouteConflict(Some(Cow::Owned(format!(
+                    "Host {} is ambiguous with already registered host {}",
+                    host, self.hosts[idx].0.pattern
+                )))));
+            }
+            None => {
+                let idx = self
+                    .hosts
+                    .iter()
+                    .position(|(p, _)| pattern.precedence(p).is_lt())
+                    .unwrap_or(self.hosts.len());
+                self.hosts.insert(idx, (pattern, URLRouter::new()));
+                idx
+            }
+        };
+        Ok(&mut self.hosts[idx].1)
+    }
+    /// Remove a already registered route.
+    pub(crate) fn remove_route(&mut self, url: &str) -> Option<Route> {
+        let segments = parse_pattern(url).ok()?;
+        let route = self.root.walk_mut(&segments)?.route.take()?;
+        self.names.retain(|_name, pattern| *pattern != route.pattern);
+        Some(route)
+    }
+}
+#[cfg(test)]
+mod routertest {
+    use super::*;
+    use crate::{utils::Method, Request::HttpRequest, Response::HttpResponse};
+    use std::sync::Arc;
+    fn handler(_req: &HttpRequest, _path_params: PathParams) -> HttpResponse {
+        unimplemented!()
+    }
+    fn route() -> Route {
+        Route::new(Arc::new(handler), "handler", vec![Method::GET])
+    }
+    fn matched(router: &URLRouter, url: &str) -> Option<(String, HashMap<String, String>)> {
+        let mut params = PathParams::new();
+        router
+            .get_route(url, Some(&mut params))
+            .map(|r| (r.pattern.clone(), params.into()))
+    }
+    #[test]
+    fn precedence_test() {
+        let mut router = URLRouter::new();
+        router.add_route("/users/{*rest}", route()).unwrap();
+        router.add_route("/users/{id}", route()).unwrap();
+        router.add_route("/users/me", route()).unwrap();
+        router.add_route("/users/{id}/orders", route()).unwrap();
+        router.add_route("/", route()).unwrap();
+        router.add_route("/items/{id}/orders", route()).unwrap();
+
+        let (pattern, _) = matched(&router, "/users/me").unwrap();
+        assert_eq!(pattern, "/users/me", "STATIC SHOULD WIN");
+        let (pattern, params) = matched(&router, "/users/5").unwrap();
+        assert_eq!(pattern, "/users/{id}", "PARAM SHOULD WIN OVER WILDCARD");
+        assert_eq!(params.get("id").map(|s| s.as_str()), Some("5"));
+        let (pattern, params) = matched(&router, "/users/me/orders").unwrap();
+        assert_eq!(pattern, "/users/{id}/orders", "BACKTRACKING FROM STATIC FAILED");
+        assert_eq!(params.get("id").map(|s| s.as_str()), Some("me"));
+        let (pattern, params) = matched(&router, "/users/5/payments/2").unwrap();
+        assert_eq!(pattern, "/users/{*rest}");
+        assert_eq!(params.get("rest").map(|s| s.as_str()), Some("5/payments/2"));
+        assert!(!params.contains_key("id"), "STALE CAPTURE AFTER BACKTRACKING");
+        assert_eq!(matched(&router, "/").unwrap().0, "/");
+        assert!(matched(&router, "/orders").is_none());
+        assert!(matched(&router, "/items//orders").is_none(), "EMPTY SEGMENT MATCHED A PARAM");
+    }
+    #[test]
+    fn conflict_test() {
+        let mut router = URLRouter::new();
+        router.add_route("/users/{id}", route()).unwrap();
+        assert!(matches!(
+            router.add_route("/users/{id}", route()),
+            Err(ApiError::RouteConflict(_))
+        ));
+        router
+            .add_route("/users/{id}", Route::new(Arc::new(handler), "handler", vec![Method::PUT, Method::DELETE]))
+            .unwrap();
+        assert!(matches!(
+            router.add_route("/users/{id}", Route::new(Arc::new(handler), "handler", vec![Method::POST, Method::PUT])),
+            Err(ApiError::RouteConflict(_))
+        ));
+        let merged = router.get_route("/users/5", None).unwrap();
+        assert_eq!(merged.allow_header(), "GET, PUT, DELETE, HEAD, OPTIONS", "FAILED CONFLICT SHOULDN'T MERGE");
+        assert!(merged.handler(&Method::POST).is_none());
+        assert!(matches!(
+            router.add_route("/users/{uid}/orders", route()),
+            Err(ApiError::RouteConflict(_))
+        ));
+        assert!(matches!(
+            router.add_route("/files/{*path}/meta", route()),
+            Err(ApiError::InvalidRoutePattern(_))
+        ));
+        assert!(matches!(
+            router.add_route("/files/img-{id}", route()),
+            Err(ApiError::InvalidRoutePattern(_))
+        ));
+        assert!(router.remove_route("/users/{id}").is_some());
+        assert!(matched(&router, "/users/5").is_none());
+        router.add_route("/users/{id}", route()).unwrap();
+    }
+    #[test]
+    fn converter_test() {
+        let mut router = URLRouter::new();
+        router.add_route("/users/{name}", route()).unwrap();
+        router.add_route("/users/{id:int}", route()).unwrap();
+        router.add_route("/users/{id:int}/orders", route()).unwrap();
+        router.add_route("/keys/{key:uuid}", route()).unwrap();
+        router.add_route("/posts/{slug:[a-z-]+}", route()).unwrap();
+        router.add_route("/codes/{code:[0-9]{3}}", route()).unwrap();
+        router.add_route("/static/{rest:path}", route()).unwrap();
+
+        assert_eq!(matched(&router, "/users/42").unwrap().0, "/users/{id:int}");
+        assert_eq!(matched(&router, "/users/-42").unwrap().0, "/users/{id:int}");
+        assert_eq!(matched(&router, "/users/rony").unwrap().0, "/users/{name}");
+        assert!(matched(&router, "/users/rony/orders").is_none(), "CONVERTER NOT APPLIED");
+        assert!(matched(&router, "/users/5/orders").is_some());
+        assert!(matched(&router, "/keys/67e55044-10b1-426f-9247-bb680e5fe0c8").is_some());
+        assert!(matched(&router, "/keys/67e55044").is_none());
+        assert!(matched(&router, "/posts/hello-world").is_some());
+        assert!(matched(&router, "/posts/Hello").is_none());
+        assert!(matched(&router, "/codes/404").is_some());
+        assert!(matched(&router, "/codes/4040").is_none());
+        let (_, params) = matched(&router, "/static/css/app.css").unwrap();
+        assert_eq!(params.get("rest").map(|s| s.as_str()), Some("css/app.css"));
+
+        assert!(matches!(
+            router.add_route("/users/{uid:int}/payments", route()),
+            Err(ApiError::RouteConflict(_))
+        ));
+        assert!(matches!(
+            router.add_route("/bad/{id:[0-9}", route()),
+            Err(ApiError::InvalidRoutePattern(_))
+        ));
+        // Constrained parameters that may accept the same segment can't share a position.
+        for url in ["/users/{price:float}", "/users/{id:[0-9]+}", "/codes/{n:int}", "/posts/{id:[0-9]+}"] {
+            assert!(
+                matches!(router.add_route(url, route()), Err(ApiError::RouteConflict(_))),
+                "OVERLAP ACCEPTED {}",
+                url
+            );
+        }
+        router.add_route("/users/{key:uuid}", route()).unwrap();
+        router.add_route("/keys/{id:int}", route()).unwrap();
+        assert_eq!(matched(&router, "/users/67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap().0, "/users/{key:uuid}");
+        assert_eq!(matched(&router, "/keys/5").unwrap().0, "/keys/{id:int}");
+        let mut params = PathParams::new();
+        router.get_route("/users/42", Some(&mut params)).unwrap();
+        assert_eq!(params.get_as::<u32>("id"), Ok(42));
+        assert!(params.get_as::<u32>("name").is_err());
+    }
+    #[test]
+    fn fallback_test() {
+        let mut router = URLRouter::new();
+        let api: RouteFunction = Arc::new(handler);
+        let users: RouteFunction = Arc::new(handler);
+        router.add_route("/api/users/{id:int}", route()).unwrap();
+        router.add_fallback("/api", api.clone()).unwrap();
+        router.add_fallback("/api/users/{id:int}", users.clone()).unwrap();
+
+        assert!(router.get_fallback("/", None).is_none(), "FALLBACK OUTSIDE IT'S PREFIX");
+        assert!(router.get_fallback("/apis", None).is_none(), "FALLBACK OUTSIDE IT'S PREFIX");
+        assert!(Arc::ptr_eq(router.get_fallback("/api", None).unwrap(), &api));
+        assert!(Arc::ptr_eq(router.get_fallback("/api/users", None).unwrap(), &api));
+        assert!(Arc::ptr_eq(router.get_fallback("/api/users/rony/x", None).unwrap(), &api));
+        let mut params = PathParams::new();
+        let fallback = router.get_fallback("/api/users/5/orders", Some(&mut params)).unwrap();
+        assert!(Arc::ptr_eq(fallback, &users), "DEEPEST FALLBACK SHOULD WIN");
+        assert_eq!(params.get("id").map(|s| s.as_str()), Some("5"));
+        assert!(matches!(
+            router.add_fallback("/api", api),
+            Err(ApiError::RouteConflict(_))
+        ));
+    }
+    #[test]
+    fn url_for_test() {
+        let mut router = URLRouter::new();
+        router.add_named_route(Some("user_detail"), "/users/{id:int}", route()).unwrap();
+        router.add_named_route(Some("files"), "/files/{*path}", route()).unwrap();
+        router.add_named_route(Some("home"), "/", route()).unwrap();
+
+        assert_eq!(router.url_for("user_detail", &[("id", "5")]).as_deref(), Some("/users/5"));
+        assert_eq!(
+            router.url_for("user_detail", &[("id", "a b/c"), ("tab", "x&y")]).as_deref(),
+            Some("/users/a%20b%2Fc?tab=x%26y")
+        );
+        assert_eq!(
+            router.url_for("files", &[("path", "docs/My File.pdf")]).as_deref(),
+            Some("/files/docs/My%20File.pdf")
+        );
+        assert_eq!(router.url_for("home", &[]).as_deref(), Some("/"));
+        assert!(router.url_for("user_detail", &[]).is_none(), "MISSING PARAM");
+        assert!(router.url_for("nope", &[]).is_none());
+        assert!(matches!(
+            router.add_named_route(Some("home"), "/home", route()),
+            Err(ApiError::RouteConflict(_))
+        ));
+        router.remove_route("/users/{id:int}").unwrap();
+        assert!(router.url_for("user_detail", &[("id", "5")]).is_none(), "NAME OF REMOVED ROUTE");
+    }
+    #[test]
+    fn routes_test() {
+        let mut router = URLRouter::new();
+        router.add_named_route(Some("user_detail"), "/users/{id:int}", route()).unwrap();
+        router
+            .add_route("/users/{id:int}", Route::new(Arc::new(handler), "delete_user", vec![Method::DELETE]))
+            .unwrap();
+        router.add_route("/files/{*path}", route()).unwrap();
+        router.add_route("/", route()).unwrap();
+
+        let routes = router.routes();
+        let patterns: Vec<&str> = routes.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, vec!["/", "/files/{*path}", "/users/{id:int}"]);
+        let user = &routes[2];
+        assert_eq!(user.name.as_deref(), Some("user_detail"));
+        assert_eq!(user.methods(), vec!["GET", "DELETE"]);
+        assert_eq!(user.handlers[1].handler, "delete_user");
+        assert_eq!(user.allow, "GET, DELETE, HEAD, OPTIONS");
+        assert!(routes[1].name.is_none());
+    }
+    #[test]
+    fn host_test() {
+        let mut router = URLRouter::new();
+        router.add_route("/", route()).unwrap();
+        router.host_mut("{tenant}.example.local").unwrap().add_route("/dashboard", route()).unwrap();
+        router.host_mut("Admin.Example.Local").unwrap().add_route("/users", route()).unwrap();
+
+        let (admin, params) = router.for_host(Some("admin.example.local:5000"));
+        assert!(admin.get_route("/users", None).is_some(), "EXACT HOST BEFORE PARAM HOST");
+        assert!(params.is_empty());
+        let (tenant, params) = router.for_host(Some("ACME.example.local"));
+        assert!(tenant.get_route("/dashboard", None).is_some());
+        assert_eq!(params, vec![(String::from("tenant"), String::from("acme"))]);
+        for host in [Some("a.b.example.local"), Some("example.local"), Some("127.0.0.1:5000"), None] {
+            let (default, params) = router.for_host(host);
+            assert!(default.get_route("/", None).is_some(), "DEFAULT HOST FOR {:?}", host);
+            assert!(params.is_empty());
+        }
+        assert!(matches!(
+            router.host_mut("{org}.example.local"),
+            Err(ApiError::RouteConflict(_))
+        ));
+        assert!(matches!(
+            router.host_mut("bad..example.local"),
+            Err(ApiError::InvalidRoutePattern(_))
+        ));
+        // A host matched by several patterns picks the most static one, whatever the order they were added in.
+        for order in [
+            ["{a}.{b}.local", "{tenant}.example.local", "api.{b}.local"],
+            ["api.{b}.local", "{tenant}.example.local", "{a}.{b}.local"],
+        ] {
+            let mut router = URLRouter::new();
+            for host in order {
+                router.host_mut(host).unwrap().add_route(&format!("/{}", host.replace(['{', '}'], "")), route()).unwrap();
+            }
+            let picked = |host| {
+                let (router, _) = router.for_host(Some(host));
+                router.routes().pop().unwrap().pattern
+            };
+            assert_eq!(picked("x.example.local"), "/tenant.example.local", "ORDER DEPENDENT HOST {:?}", order);
+            assert_eq!(picked("api.example.local"), "/api.b.local", "ORDER DEPENDENT HOST {:?}", order);
+            assert_eq!(picked("x.y.local"), "/a.b.local");
+        }
+        let hosts: Vec<Option<String>> = router.routes().into_iter().map(|r| r.host).collect();
+        assert_eq!(
+            hosts,
+            vec![
+                None,
+                Some(String::from("admin.example.local")),
+                Some(String::from("{tenant}.example.local"))
+            ]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! ## Sub router
+//!
+//! A **Router** is a group of routes that can be built on it's own, e.g. in the module of a feature,
+//! and mounted under a url prefix with `RastAPI::mount`. Routers can be mounted inside other routers, prefixes get joined.
+//!
+//! Middleware and the fallback of a router only apply to the routes mounted through it.
+use super::{
+    parse_methods, route_error, route_function, router::validate_pattern, Route, RouteFunction,
+};
+use crate::{
+    utils::Method,
+    Extract::Handler,
+    Request::{HttpRequest, PathParams},
+    Response::HttpResponse,
+};
+use std::{any::type_name, io, sync::Arc};
+
+/// The rest of the handler chain, passed to a middleware. Call it to run the route handler.
+pub type Next<'a> = &'a dyn Fn(&HttpRequest, PathParams) -> HttpResponse;
+/// A function that runs around every handler of a router.
+type Middleware = Arc<dyn Fn(&HttpRequest, PathParams, Next) -> HttpResponse + Send + Sync>;
+/// Absolute url, name and route of a flattened route, ready to be added to the **URLRouter**.
+pub(crate) type MountedRoute = (String, Option<String>, Route);
+/// Absolute prefix and handler of a flattened fallback.
+type FlatFallback = (String, RouteFunction);
+/// A route registered on a router but not yet mounted.
+struct PendingRoute {
+    url: String,
+    name: Option<String>,
+    methods: Vec<Method>,
+    func: RouteFunction,
+    /// Type name of the handler, see `RouteInfo`.
+    handler_name: &'static str,
+}
+/// ## Router
+/// A group of routes with it's own middleware and fallback. See `RastAPI::mount`.
+///
+/// ## Example
+/// ```no_run
+/// use rastapi::{RastAPI, Router, Next};
+/// use rastapi::Request::{HttpRequest,PathParams};
+/// use rastapi::Response::{HttpResponse,create_response};
+/// use rastapi::utils::ContentType;
+///
+/// fn user(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+///     create_response(&format!("User {}",path_params["id"]),200,ContentType::TEXT,false).unwrap()
+/// }
+/// fn users_router()->Router{
+///     let mut users=Router::new();
+///     users.register_route("/users/{id:int}",vec!["GET"],user).unwrap();
+///     // Runs only for the routes of this router.
+///     users.middleware(|req:&HttpRequest,path_params:PathParams,next:Next|{
+///         let mut resp=next(req,path_params);
+///         resp.add_header("X-Api-Version","1");
+///         resp
+///     });
+///     // Answers every unmatched url under the mount prefix.
+///     users.fallback(||create_response(r#"{"error":"not found"}"#,404,ContentType::JSON,false).unwrap());
+///     users
+/// }
+/// fn main(){
+///     let mut app=RastAPI::new();
+///     app.mount("/api/v1",users_router()).unwrap(); // GET /api/v1/users/5
+///     app.run("127.0.0.1",5000);
+/// }
+/// ```
+pub struct Router {
+    routes: Vec<PendingRoute>,
+    children: Vec<(String, Router)>,
+    /// Middleware in the order they were added. The first one is the outermost.
+    middleware: Vec<Middleware>,
+    fallback: Option<RouteFunction>,
+}
+impl Router {
+    /// Create an empty router.
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            children: Vec::new(),
+            middleware: Vec::new(),
+            fallback: None,
+        }
+    }
+    /// Register a route on this router. `url` is relative to the prefix the router gets mounted on.
+    /// Takes the same handlers as `RastAPI::register_route`.
+    ///
+    /// Can return error if supplied method is not implimented or if the url is not a valid route pattern.
+    /// Conflicts with other routes are detected when the router is mounted on the app.
+    pub fn register_route<F, Args>(
+        &mut self,
+        url: &str,
+        methods: Vec<&str>,
+        func: F,
+    ) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        self.push_route(None, url, methods, route_function(func), type_name::<F>())
+    }
+    /// Register a route with a name, see `RastAPI::register_named_route`.
+    /// Names must be unique across the whole app, conflicts are detected when the router is mounted.
+    pub fn register_named_route<F, Args>(
+        &mut self,
+        name: &str,
+        url: &str,
+        methods: Vec<&str>,
+        func: F,
+    ) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        self.push_route(Some(name), url, methods, route_function(func), type_name::<F>())
+    }
+    fn push_route(
+        &mut self,
+        name: Option<&str>,
+        url: &str,
+        methods: Vec<&str>,
+        func: RouteFunction,
+        handler_name: &'static str,
+    ) -> Result<(), io::Error> {
+        let methods = parse_methods(methods)?;
+        validate_pattern(url).map_err(route_error)?;
+        self.routes.push(PendingRoute {
+            url: url.to_string(),
+            name: name.map(|n| n.to_string()),
+            methods,
+            func,
+            handler_name,
+        });
+        Ok(())
+    }
+    /// Mount another router under `prefix`, relative to the prefix of this router.
+    pub fn mount(&mut self, prefix: &str, router: Router) {
+        self.children.push((prefix.to_string(), router));
+    }
+    /// Add a middleware. It runs around every handler of this router and of the routers mounted in it,
+    /// and can inspect the request, short circuit with it's own response or change the response of `next`.
+    /// Middleware run in the order they were added.
+    pub fn middleware<M>(&mut self, middleware: M)
+    where
+        M: Fn(&HttpRequest, PathParams, Next) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+    }
+    /// Set the handler for urls under the mount prefix that don't match any route.
+    /// A fallback of a router mounted deeper takes precedence.
+    pub fn fallback<F, Args>(&mut self, func: F)
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        self.fallback = Some(route_function(func));
+    }
+    /// Turn this router mounted on `prefix` into absolute routes and fallbacks, with the middleware applied.
+    pub(crate) fn flatten(self, prefix: &str) -> (Vec<MountedRoute>, Vec<FlatFallback>) {
+        let (routes, fallbacks) = self.collect(prefix);
+        let routes = routes
+            .into_iter()
+            .map(|r| (r.url, r.name, Route::new(r.func, r.handler_name, r.methods)))
+            .collect();
+        (routes, fallbacks)
+    }
+    fn collect(self, prefix: &str) -> (Vec<PendingRoute>, Vec<FlatFallback>) {
+        let mut routes: Vec<PendingRoute> = self
+            .routes
+            .into_iter()
+            .map(|r| PendingRoute {
+                url: join_prefix(prefix, &r.url),
+                ..r
+            })
+            .collect();
+        let mut fallbacks = Vec::new();
+        for (child_prefix, child) in self.children {
+            let (child_routes, child_fallbacks) = child.collect(&join_prefix(prefix, &child_prefix));
+            routes.extend(child_routes);
+            fallbacks.extend(child_fallbacks);
+        }
+        if let Some(fallback) = self.fallback {
+            fallbacks.push((join_prefix(prefix, "/"), fallback));
+        }
+        // Wrap from the innermost middleware out, so the first one added runs first.
+        for middleware in self.middleware.iter().rev() {
+            for route in routes.iter_mut() {
+                route.func = wrap(middleware, route.func.clone());
+            }
+            for (_prefix, func) in fallbacks.iter_mut() {
+                *func = wrap(middleware, func.clone());
+            }
+        }
+        (routes, fallbacks)
+    }
+}
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+fn wrap(middleware: &Middleware, func: RouteFunction) -> RouteFunction {
+    let middleware = middleware.clone();
+    Arc::new(move |req: &HttpRequest, path_params: PathParams| {
+        middleware(req, path_params, &*func)
+    })
+}
+/// Join a mount prefix and a url, e.g. `/api/v1` and `/users` make `/api/v1/users`.
+pub(crate) fn join_prefix(prefix: &str, url: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    let url = url.trim_start_matches('/');
+    match (prefix.is_empty(), url.is_empty()) {
+        (true, true) => String::from("/"),
+        (true, false) => format!("/{}", url),
+        (false, true) => format!("/{}", prefix),
+        (false, false) => format!("/{}/{}", prefix, url),
+    }
+}
@@ -0,0 +1,415 @@
+//! ## Extract
+//!
+//! Extractors pull typed values out of an incoming request, so a route handler can take only what it needs as arguments
+//! instead of digging through **HttpRequest** by hand.
+//! Any type implementing **FromRequest** can be a handler argument, handlers can take up to 8 of them.
+//! If an extractor fails the handler isn't called, the request gets an automatic response instead:
+//!  - `Path` values that fail to parse get a 422 Unprocessable Entity.
+//!  - `Query` values that are missing or fail to parse, a missing header, body, uploaded file or `Form` get a 400 Bad Request.
+//!  - A `State` that was never added to the app gets a 500 Internal Server Error.
+//!
+//! Wrap an extractor in `Option` to make it optional.
+//!
+//! ## Example
+//! ```no_run
+//! use rastapi::RastAPI;
+//! use rastapi::Extract::{Body, Header, Path, Query, State, FromQuery};
+//! use rastapi::Request::{ParamError, QueryParams};
+//! use rastapi::Response::{HttpResponse,create_response};
+//! use rastapi::utils::ContentType;
+//! use rastapi::header_name;
+//!
+//! header_name!(ApiKey, "x-api-key");
+//! struct Db{ name:String }
+//! struct Paging{ page:u32, tags:Vec<String> }
+//! impl FromQuery for Paging{
+//!     fn from_query(params:&QueryParams)->Result<Self,ParamError>{
+//!         Ok(Paging{
+//!             page:params.get_opt_as("page")?.unwrap_or(1),
+//!             tags:params.get_all_as("tag")?,
+//!         })
+//!     }
+//! }
+//! // Registered as "/users/{id:int}/posts"
+//! fn posts(Path(id):Path<u64>,Query(paging):Query<Paging>,key:Header<ApiKey>,db:State<Db>)->HttpResponse{
+//!     let msg=format!("{} : posts of user {}, page {} of {:?}, key {}",db.name,id,paging.page,paging.tags,key.as_str());
+//!     create_response(&msg,200,ContentType::TEXT,false).unwrap()
+//! }
+//! fn echo(Body(body):Body<String>)->HttpResponse{
+//!     create_response(&body,200,ContentType::TEXT,false).unwrap()
+//! }
+//! fn main(){
+//!     let mut app=RastAPI::new();
+//!     app.with_state(Db{ name:String::from("main") });
+//!     app.register_route("/users/{id:int}/posts",vec!["GET"],posts).unwrap();
+//!     app.register_route("/echo",vec!["POST"],echo).unwrap();
+//!     app.run("127.0.0.1",5000);
+//! }
+//! ```
+use crate::{
+    error,
+    utils::ContentType,
+    Request::{Form, HttpRequest, ParamError, PathParams, QueryParams},
+    Response::{
+        pre_built_resp::{BadRequest, InternalServerError, UnprocessableEntity},
+        HttpResponse,
+    },
+};
+use std::{collections::HashMap, marker::PhantomData, ops::Deref, path::PathBuf, sync::Arc};
+
+/// Types that can be built from an incoming request and used as route handler arguments.
+/// On failure return the response that should be sent instead of calling the handler.
+#[allow(clippy::result_large_err)]
+pub trait FromRequest: Sized {
+    fn from_request(req: &HttpRequest, path_params: &PathParams) -> Result<Self, HttpResponse>;
+}
+/// Route handlers. It's implemented for functions and closures of the form
+/// `Fn(&HttpRequest,PathParams)->HttpResponse`, for ones of the older form `Fn(&HttpRequest,HashMap<String,String>)->HttpResponse`
+/// and for ones taking 0 to 8 **FromRequest** arguments.
+/// `Args` only exists to tell the implementations apart, it's inferred by the compiler.
+pub trait Handler<Args>: Send + Sync + 'static {
+    fn call(&self, req: &HttpRequest, path_params: PathParams) -> HttpResponse;
+}
+/// Marker for handlers taking the raw request and path parameters.
+#[doc(hidden)]
+pub struct RawHandler;
+impl<F> Handler<RawHandler> for F
+where
+    F: Fn(&HttpRequest, PathParams) -> HttpResponse + Send + Sync + 'static,
+{
+    fn call(&self, req: &HttpRequest, path_params: PathParams) -> HttpResponse {
+        self(req, path_params)
+    }
+}
+/// Marker for handlers taking the raw request and the path parameters as a plain `HashMap`.
+#[doc(hidden)]
+pub struct MapHandler;
+impl<F> Handler<MapHandler> for F
+where
+    F: Fn(&HttpRequest, HashMap<String, String>) -> HttpResponse + Send + Sync + 'static,
+{
+    fn call(&self, req: &HttpRequest, path_params: PathParams) -> HttpResponse {
+        self(req, path_params.into())
+    }
+}
+macro_rules! impl_handler {
+    ($($T:ident),*) => {
+        #[allow(non_snake_case, unused_variables)]
+        impl<F, $($T,)*> Handler<($($T,)*)> for F
+        where
+            F: Fn($($T),*) -> HttpResponse + Send + Sync + 'static,
+            $($T: FromRequest,)*
+        {
+            fn call(&self, req: &HttpRequest, path_params: PathParams) -> HttpResponse {
+                $(
+                    let $T = match $T::from_request(req, &path_params) {
+                        Ok(v) => v,
+                        Err(resp) => return resp,
+                    };
+                )*
+                self($($T),*)
+            }
+        }
+    };
+}
+impl_handler!();
+impl_handler!(A);
+impl_handler!(A, B);
+impl_handler!(A, B, C);
+impl_handler!(A, B, C, D);
+impl_handler!(A, B, C, D, E);
+impl_handler!(A, B, C, D, E, G);
+impl_handler!(A, B, C, D, E, G, H);
+impl_handler!(A, B, C, D, E, G, H, I);
+
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(req: &HttpRequest, path_params: &PathParams) -> Result<Self, HttpResponse> {
+        Ok(T::from_request(req, path_params).ok())
+    }
+}
+impl FromRequest for PathParams {
+    fn from_request(_req: &HttpRequest, path_params: &PathParams) -> Result<Self, HttpResponse> {
+        Ok(path_params.clone())
+    }
+}
+
+/// Types that can be built from url path parameters. See **Path**.
+/// Implemented for common scalar types (taking the first parameter), tuples of them (taking parameters in the order
+/// they appear in the route pattern), `PathParams` and `HashMap<String,String>`.
+/// Implement it for your own struct to pick parameters by name with `PathParams::get_as`.
+pub trait FromPathParams: Sized {
+    fn from_path_params(path_params: &PathParams) -> Result<Self, ParamError>;
+}
+/// Parse the `i`th path parameter into `T`.
+fn nth_param<T: std::str::FromStr>(path_params: &PathParams, i: usize) -> Result<T, ParamError> {
+    match path_params.nth(i) {
+        Some((name, val)) => val.parse::<T>().map_err(|_e| ParamError {
+            name: name.to_string(),
+            msg: format!("invalid value {:?}", val),
+        }),
+        None => Err(ParamError {
+            name: format!("#{}", i),
+            msg: String::from("missing path parameter"),
+        }),
+    }
+}
+macro_rules! impl_from_path_params_scalar {
+    ($($T:ty),*) => {
+        $(
+            impl FromPathParams for $T {
+                fn from_path_params(path_params: &PathParams) -> Result<Self, ParamError> {
+                    nth_param(path_params, 0)
+                }
+            }
+        )*
+    };
+}
+impl_from_path_params_scalar!(
+    String, bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+macro_rules! impl_from_path_params_tuple {
+    ($($T:ident : $i:tt),*) => {
+        impl<$($T: std::str::FromStr,)*> FromPathParams for ($($T,)*) {
+            fn from_path_params(path_params: &PathParams) -> Result<Self, ParamError> {
+                Ok(($(nth_param::<$T>(path_params, $i)?,)*))
+            }
+        }
+    };
+}
+impl_from_path_params_tuple!(A: 0);
+impl_from_path_params_tuple!(A: 0, B: 1);
+impl_from_path_params_tuple!(A: 0, B: 1, C: 2);
+impl_from_path_params_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl_from_path_params_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_from_path_params_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, G: 5);
+impl FromPathParams for PathParams {
+    fn from_path_params(path_params: &PathParams) -> Result<Self, ParamError> {
+        Ok(path_params.clone())
+    }
+}
+impl FromPathParams for HashMap<String, String> {
+    fn from_path_params(path_params: &PathParams) -> Result<Self, ParamError> {
+        Ok(path_params.clone().into())
+    }
+}
+/// Typed url path parameters, e.g. `Path<u64>` for `/users/{id}` or `Path<(String,u64)>` for `/{team}/{id}`.
+pub struct Path<T>(pub T);
+impl<T> Path<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+impl<T> Deref for Path<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+impl<T: FromPathParams> FromRequest for Path<T> {
+    fn from_request(_req: &HttpRequest, path_params: &PathParams) -> Result<Self, HttpResponse> {
+        match T::from_path_params(path_params) {
+            Ok(v) => Ok(Path(v)),
+            Err(e) => Err(UnprocessableEntity(&format!("Invalid path parameter {}", e))),
+        }
+    }
+}
+
+/// Types that can be built from url query parameters. See **Query** and `HttpRequest::query`.
+/// Implemented for **QueryParams**, `HashMap<String,String>` (the first value of every parameter) and
+/// `HashMap<String,Vec<String>>`. Implement it for your own struct to pick and parse parameters.
+pub trait FromQuery: Sized {
+    fn from_query(params: &QueryParams) -> Result<Self, ParamError>;
+}
+impl FromQuery for QueryParams {
+    fn from_query(params: &QueryParams) -> Result<Self, ParamError> {
+        Ok(params.clone())
+    }
+}
+impl FromQuery for HashMap<String, String> {
+    fn from_query(params: &QueryParams) -> Result<Self, ParamError> {
+        let mut map = HashMap::new();
+        for (name, val) in params.iter() {
+            map.entry(name.to_string()).or_insert_with(|| val.to_string());
+        }
+        Ok(map)
+    }
+}
+impl FromQuery for HashMap<String, Vec<String>> {
+    fn from_query(params: &QueryParams) -> Result<Self, ParamError> {
+        let mut map: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, val) in params.iter() {
+            map.entry(name.to_string()).or_default().push(val.to_string());
+        }
+        Ok(map)
+    }
+}
+/// Typed url query parameters, e.g. `page` in `/users?page=2`.
+pub struct Query<T>(pub T);
+impl<T> Query<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+impl<T> Deref for Query<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+impl<T: FromQuery> FromRequest for Query<T> {
+    fn from_request(req: &HttpRequest, _path_params: &PathParams) -> Result<Self, HttpResponse> {
+        match req.query::<T>() {
+            Ok(v) => Ok(Query(v)),
+            Err(e) => Err(BadRequest(&format!("Invalid query parameter {}", e))),
+        }
+    }
+}
+
+/// Name of a header to extract with **Header**. Define one with the `header_name!` macro.
+pub trait HeaderName {
+    const NAME: &'static str;
+}
+/// Define a **HeaderName** marker type to be used with **Header**.
+/// ## Example
+/// ```
+/// use rastapi::header_name;
+/// use rastapi::Extract::HeaderName;
+/// header_name!(ApiKey, "x-api-key");
+/// assert_eq!(ApiKey::NAME, "x-api-key");
+/// ```
+#[macro_export]
+macro_rules! header_name {
+    ($name:ident, $header:expr) => {
+        pub struct $name;
+        impl $crate::Extract::HeaderName for $name {
+            const NAME: &'static str = $header;
+        }
+    };
+}
+/// Value of the request header named by `N`. Header names are matched case insensitively.
+pub struct Header<N: HeaderName> {
+    value: String,
+    _name: PhantomData<N>,
+}
+impl<N: HeaderName> Header<N> {
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+    pub fn into_inner(self) -> String {
+        self.value
+    }
+}
+impl<N: HeaderName> Deref for Header<N> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.value
+    }
+}
+impl<N: HeaderName> FromRequest for Header<N> {
+    fn from_request(req: &HttpRequest, _path_params: &PathParams) -> Result<Self, HttpResponse> {
+        match req.headers.get(N::NAME) {
+            Some(v) => Ok(Header {
+                value: v.to_string(),
+                _name: PhantomData,
+            }),
+            None => Err(BadRequest(&format!("Missing header {}", N::NAME))),
+        }
+    }
+}
+
+/// Types that can be built from the request body. See **Body**.
+/// Implemented for `String` and `Vec<u8>`.
+#[allow(clippy::result_large_err)]
+pub trait FromBody: Sized {
+    fn from_body(bytes: Vec<u8>) -> Result<Self, HttpResponse>;
+}
+impl FromBody for Vec<u8> {
+    fn from_body(bytes: Vec<u8>) -> Result<Self, HttpResponse> {
+        Ok(bytes)
+    }
+}
+impl FromBody for String {
+    fn from_body(bytes: Vec<u8>) -> Result<Self, HttpResponse> {
+        String::from_utf8(bytes).map_err(|_e| BadRequest("Request body is not valid UTF-8."))
+    }
+}
+/// The request body, whether it was kept in memory or streamed to disk.
+pub struct Body<T>(pub T);
+impl<T> Body<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+impl<T> Deref for Body<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+impl<T: FromBody> FromRequest for Body<T> {
+    fn from_request(req: &HttpRequest, _path_params: &PathParams) -> Result<Self, HttpResponse> {
+        if !req.has_body() {
+            return Err(BadRequest("Request body is missing."));
+        }
+        let bytes = match req.body_bytes() {
+            Ok(b) => b,
+            Err(e) => {
+                error!("{}", e);
+                return Err(InternalServerError("Couldn't read request body."));
+            }
+        };
+        T::from_body(bytes).map(Body)
+    }
+}
+
+/// A request body that was streamed to disk, e.g. an uploaded image or pdf.
+pub struct UploadedFile {
+    /// Location of the file.
+    pub path: PathBuf,
+    /// Content type of the upload.
+    pub content_type: Option<ContentType>,
+    /// Size of the upload in bytes.
+    pub size: u64,
+}
+impl FromRequest for UploadedFile {
+    fn from_request(req: &HttpRequest, _path_params: &PathParams) -> Result<Self, HttpResponse> {
+        match &req.body_location {
+            Some(loc) => Ok(UploadedFile {
+                path: loc.clone(),
+                content_type: req.content_type,
+                size: req.content_len.unwrap_or(0),
+            }),
+            None => Err(BadRequest("No file uploaded.")),
+        }
+    }
+}
+
+/// A `multipart/form-data` body, see `HttpRequest::form`. Requests with another body get a 400 Bad Request.
+impl FromRequest for Form {
+    fn from_request(req: &HttpRequest, _path_params: &PathParams) -> Result<Self, HttpResponse> {
+        match req.form() {
+            Some(form) => Ok(form.clone()),
+            None => Err(BadRequest("Expected a multipart/form-data body.")),
+        }
+    }
+}
+
+/// Shared application state of type `T`, added with `RastAPI::with_state`.
+pub struct State<T>(pub Arc<T>);
+impl<T> Deref for State<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+impl<T: Send + Sync + 'static> FromRequest for State<T> {
+    fn from_request(req: &HttpRequest, _path_params: &PathParams) -> Result<Self, HttpResponse> {
+        match req.state_arc::<T>() {
+            Some(s) => Ok(State(s)),
+            None => {
+                error!("State {} was never added to the app.", std::any::type_name::<T>());
+                Err(InternalServerError("Internal server error."))
+            }
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! ## Body
+//!
+//! Readers for request bodies. A body is either sized by `Content-Length` or sent with `Transfer-Encoding: chunked`,
+//! both are read through a **BodyReader** which stops at the end of the body, so the next request on the connection is untouched.
+use crate::{
+    error, log_info,
+    utils::{error::ApiError, HeaderMap},
+};
+use std::{
+    borrow::Cow,
+    cmp::min,
+    error::Error,
+    fmt,
+    io::{self, BufRead, Read, Take},
+};
+/// Maximum length of a chunk size line or a trailer line.
+const CHUNK_LINE_LIMIT: u64 = 8192;
+
+/// Why a body couldn't be decoded, carried inside an `io::Error`.
+#[derive(Debug)]
+pub(crate) enum BodyError {
+    /// The decoded body is larger than the maximum payload size.
+    TooLarge,
+    Malformed(&'static str),
+}
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge => write!(f, "Payload Too large."),
+            Self::Malformed(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+impl Error for BodyError {}
+/// Map an error of reading a body to the error answered to the client.
+pub(crate) fn body_error(e: io::Error) -> ApiError<'static> {
+    if let Some(body_err) = e.get_ref().and_then(|inner| inner.downcast_ref::<BodyError>()) {
+        return match body_err {
+            BodyError::TooLarge => ApiError::PayloadTooLarge(None),
+            BodyError::Malformed(msg) => ApiError::MalformedBody(Some(Cow::Borrowed(msg))),
+        };
+    }
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            error!("Read time out reached.\n{}", e);
+            ApiError::RequestTimedout(None)
+        }
+        io::ErrorKind::UnexpectedEof => {
+            error!("UnexpectedEOF : Connection may be dropped prematurely.");
+            ApiError::ClientDisconnected(None)
+        }
+        _ => {
+            error!("{}", e);
+            ApiError::RequestBodyNotRead(None)
+        }
+    }
+}
+fn malformed(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, BodyError::Malformed(msg))
+}
+/// Decodes a `Transfer-Encoding: chunked` body, i.e. chunks prefixed by their size in hex, ended by a zero sized chunk
+/// and optional trailer headers.
+pub(crate) struct ChunkedReader<R> {
+    inner: R,
+    /// Bytes left in the current chunk.
+    remaining: u64,
+    /// The last chunk and the trailers were read.
+    done: bool,
+    /// Size of the body decoded so far, counting the chunk being read.
+    decoded: u64,
+    /// Maximum size of the decoded body.
+    limit: u64,
+    trailers: HeaderMap,
+}
+impl<R: BufRead> ChunkedReader<R> {
+    pub(crate) fn new(inner: R, limit: u64) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            done: false,
+            decoded: 0,
+            limit,
+            trailers: HeaderMap::new(),
+        }
+    }
+    /// Read a line ended by CRLF, without the line break.
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        (&mut self.inner)
+            .take(CHUNK_LINE_LIMIT)
+            .read_until(b'\n', &mut line)?;
+        match line.strip_suffix(b"\r\n") {
+            Some(line) => String::from_utf8(line.to_vec())
+                .map_err(|_e| malformed("Chunk line is not UTF-8.")),
+            None if line.len() as u64 == CHUNK_LINE_LIMIT => Err(malformed("Chunk line too long.")),
+            None if line.ends_with(b"\n") => Err(malformed("Chunk line without CRLF.")),
+            None => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+    /// Read the size line of the next chunk. After the last chunk the trailers are read.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let line = self.read_line()?;
+        // Chunk extensions, e.g. `1a;name=value`, are ignored.
+        let size = line.split(';').next().unwrap_or("").trim();
+        if size.is_empty() || size.len() > 15 {
+            return Err(malformed("Invalid chunk size."));
+        }
+        let size = u64::from_str_radix(size, 16).map_err(|_e| malformed("Invalid chunk size."))?;
+        if size > 0 {
+            self.decoded += size;
+            if self.decoded > self.limit {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, BodyError::TooLarge));
+            }
+            self.remaining = size;
+            return Ok(());
+        }
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            match line.split_once(':') {
+                Some((key, val)) => self.trailers.append(key.trim(), val.trim()),
+                None => return Err(malformed("Invalid trailer.")),
+            }
+            if self.trailers.len() > 100 {
+                return Err(malformed("Too many trailers."));
+            }
+        }
+        self.done = true;
+        Ok(())
+    }
+}
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.next_chunk()?;
+            if self.done {
+                return Ok(0);
+            }
+        }
+        let len = min(buf.len() as u64, self.remaining) as usize;
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= n as u64;
+        if self.remaining == 0 && !self.read_line()?.is_empty() {
+            return Err(malformed("Chunk longer than it's size."));
+        }
+        Ok(n)
+    }
+}
+/// The body of a request, read up to it's end and no further.
+pub(crate) enum BodyReader<R> {
+    /// Body of `Content-Length` bytes.
+    Sized(Take<R>),
+    Chunked(ChunkedReader<R>),
+}
+impl<R: BufRead> BodyReader<R> {
+    /// Trailers sent after a chunked body. None for a sized body.
+    pub(crate) fn into_trailers(self) -> Option<HeaderMap> {
+        match self {
+            Self::Sized(_) => None,
+            Self::Chunked(chunked) => Some(chunked.trailers),
+        }
+    }
+    /// Size of the body read so far.
+    pub(crate) fn decoded_len(&self) -> Option<u64> {
+        match self {
+            Self::Sized(_) => None,
+            Self::Chunked(chunked) => Some(chunked.decoded),
+        }
+    }
+}
+impl<R: BufRead> Read for BodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Sized(take) => {
+                let n = take.read(buf)?;
+                // The connection was closed before the whole body was sent.
+                if n == 0 && !buf.is_empty() && take.limit() > 0 {
+                    log_info!("Client disconnected before sending the whole body.");
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(n)
+            }
+            Self::Chunked(chunked) => chunked.read(buf),
+        }
+    }
+}
+#[cfg(test)]
+mod bodytest {
+    use super::*;
+    #[test]
+    fn chunked_test() {
+        let body = "5\r\nHello\r\n7;ext=1\r\n, world\r\n0\r\nX-Checksum: abc\r\n\r\nNEXT REQUEST";
+        let mut rdr = io::BufReader::new(body.as_bytes());
+        let mut chunked = ChunkedReader::new(&mut rdr, 100);
+        let mut decoded = String::new();
+        chunked.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "Hello, world");
+        assert_eq!(chunked.decoded, 12);
+        assert_eq!(chunked.trailers.get("x-checksum"), Some("abc"));
+        let mut rest = String::new();
+        rdr.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "NEXT REQUEST", "READ PAST THE END OF THE BODY");
+
+        let mut too_large = ChunkedReader::new("ff\r\n".as_bytes(), 100);
+        let err = too_large.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(body_error(err), ApiError::PayloadTooLarge(_)));
+        for invalid in ["zz\r\nab\r\n0\r\n\r\n", "2\r\nabc\r\n0\r\n\r\n", "2\nab\r\n0\r\n\r\n"] {
+            let err = ChunkedReader::new(invalid.as_bytes(), 100)
+                .read_to_end(&mut Vec::new())
+                .unwrap_err();
+            assert!(matches!(body_error(err), ApiError::MalformedBody(_)), "{:?} ACCEPTED", invalid);
+        }
+        let err = ChunkedReader::new("5\r\nHel".as_bytes(), 100)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
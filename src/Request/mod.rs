@@ -3,27 +3,35 @@
 //!
 //! This module parses the incoming HTTP requests and serialize it in a **HttpRequest** struct.
 //! Later this **HttpRequest** Struct is passed as the first parameter to the specific route handler function (**RouterFunction**).
+mod body;
+mod multipart;
+mod params;
+mod uploads;
+pub use multipart::{Form, FormFile};
+pub use params::{ParamError, PathParams, QueryParams};
+use body::{body_error, BodyReader, ChunkedReader};
+use multipart::{header_param, parse_multipart, MultipartLimits};
+pub(crate) use uploads::{sweep_incoming_dir, DiskQuota};
+use uploads::TempFiles;
 use crate::{
-    error, log_info, log_response_4xx,
-    utils::{error::ApiError, ContentType, FileType, Method, Protocall},
-    App::AppEnv,
+    error, log_info, log_response_3xx_content, log_response_4xx,
+    utils::{
+        decode_path, encode_path, error::ApiError, ContentType, CookieJar, FileType, HeaderMap,
+        Method, Protocall, TrailingSlash,
+    },
+    App::{host::host_name, AppEnv, URLRouter},
+    Extract::FromQuery,
 };
-use rand::{distributions::Alphanumeric, Rng};
 use std::{
+    any::TypeId,
     borrow::Cow,
-    cmp::min,
-    collections::HashMap,
-    env,
     fs::{self, File},
-    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    io::{self, BufRead, BufReader, Read, Write},
     net::{SocketAddr, TcpStream},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
     time::Instant,
 };
-/// Maximum size of textual type (text,json) content to hold inside a variable in memory
-/// After this limit such body contents will be streamed inside a disk.
-const TEXTUAL_CONTENT_MEMORY_LIMIT: u64 = 0; //1MB
 /// Chunk size of bytes read per iteration.
 const BODY_READ_SPEED: u64 = 8192; //4KB
 /// ## HttpRequest
@@ -35,20 +43,48 @@ pub struct HttpRequest {
     pub method: String,
     /// IP address of our client. Can be None, If it's None then current request is dropped.
     pub client: Option<SocketAddr>,
-    /// Quaried resource, e.g. url
+    /// Quaried resource, e.g. url. It's percent-decoded and normalized, i.e. `/a//b/../c%20d` becomes `/a/c d`.
+    /// A `/` or `%` encoded inside a segment stays encoded as `%2F` or `%25`, e.g. `/users/a%2Fb` stays as it is,
+    /// while the path parameter matched by it is decoded to `a/b`.
     pub resource: String,
-    /// Request parameters e.g. example.com?foo=bar
-    pub params: Option<HashMap<String, String>>,
-    /// Request Body in case of JSON/TEXT based payload. Can be None if payload is not JSON or Text.
+    /// The request target exactly as it was recieved, including the query string.
+    pub raw_target: String,
+    /// Query parameters, e.g. `foo` in `example.com?foo=bar`. Empty if the url has no query.
+    pub params: QueryParams,
+    /// Request Body in case of a JSON/TEXT/YAML or form payload within the in-memory body limit, see `RastAPI::set_in_memory_body_limit`.
+    /// None for other payloads. `body_bytes`, `body_text` and `body_reader` read the body wherever it is.
     pub body: Option<String>,
-    /// Request Body location in disk, in case of file based payload. Can be None if payload is JSON or Text type.
+    /// Request Body location in disk, in case of a file based payload or a textual one larger than the in-memory body limit.
+    /// It's a temporary file, removed once the response is sent. Keep it with `persist_upload`.
+    /// It still names the temporary file after `persist_upload`, which moved it, while `body_bytes`, `body_text`
+    /// and `body_reader` read it from it's destination.
     pub body_location: Option<PathBuf>,
     /// Content Type of the payload.
     pub content_type: Option<ContentType>,
     /// Content Length of payload.
     pub content_len: Option<u64>,
     /// Headers of payload.
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
+    /// Fields and files of a `multipart/form-data` body. None for other bodies.
+    pub(crate) form: Option<Form>,
+    /// Trailer headers sent after a chunked body. Empty for other bodies.
+    pub(crate) trailers: HeaderMap,
+    /// The body is sent with `Transfer-Encoding: chunked`.
+    pub(crate) chunked: bool,
+    /// The route doesn't allow the method, and a user defined 405 handler answers it.
+    pub(crate) method_not_allowed: bool,
+    /// The body was never read, i.e. the client waited for a `100 Continue` that wasn't sent.
+    /// The connection is closed after the response, since the client may still send it.
+    pub(crate) body_unread: bool,
+    /// Fields of an `application/x-www-form-urlencoded` body. Empty for other bodies.
+    pub(crate) form_fields: QueryParams,
+    /// Files the body was streamed to, removed when the request is dropped unless they are persisted.
+    pub(crate) uploads: TempFiles,
+    /// Environment of the app that recieved this request. None if the request wasn't read from a stream.
+    pub(crate) app_env: Option<Arc<AppEnv>>,
+    /// Snapshot of the routes taken when the request line was read. The whole request is answered with it,
+    /// even if routes are changed through a **RouterHandle** meanwhile.
+    pub(crate) routes: Option<Arc<URLRouter>>,
 }
 impl HttpRequest {
     /// Create a new **HttpRequest** object.
@@ -58,15 +94,156 @@ impl HttpRequest {
             method: String::from(""),
             client: None,
             resource: String::from(""),
-            params: None,
+            raw_target: String::from(""),
+            params: QueryParams::new(),
             body: None,
             body_location: None,
             content_type: None,
             content_len: None,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
+            form: None,
+            form_fields: QueryParams::new(),
+            trailers: HeaderMap::new(),
+            chunked: false,
+            method_not_allowed: false,
+            body_unread: false,
+            uploads: TempFiles::new(None),
+            app_env: None,
+            routes: None,
         };
         return request_map;
     }
+    /// Borrow the shared state of type `T` added with `RastAPI::with_state`.
+    /// Returns None if no state of this type was added.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.app_env
+            .as_ref()?
+            .state
+            .get(&TypeId::of::<T>())?
+            .downcast_ref::<T>()
+    }
+    /// Build the url of a named route, see `RastAPI::url_for`.
+    /// e.g. `req.url_for("user_detail",&[("id","5")])` gives `/users/5` for a route registered as `/users/{id}`.
+    /// Routes of the virtual host of the request are looked up first, then the ones of the default host.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let routes = self.routes.as_ref()?;
+        let (router, _host_params) = routes.for_host(self.host().as_deref());
+        router
+            .url_for(name, params)
+            .or_else(|| routes.url_for(name, params))
+    }
+    /// Build `T` from the query parameters, see **FromQuery**.
+    /// The error names the parameter that is missing or invalid. The `Query` extractor answers it with a 400 Bad Request.
+    pub fn query<T: FromQuery>(&self) -> Result<T, ParamError> {
+        T::from_query(&self.params)
+    }
+    /// Cookies sent with the request, parsed from it's `Cookie` headers.
+    /// e.g. `req.cookies().get("sid")`
+    pub fn cookies(&self) -> CookieJar {
+        CookieJar::parse(self.headers.get_all("Cookie").into_iter())
+    }
+    /// Host name the request was sent to, i.e. the `Host` header without the port, lowercased.
+    /// None if the request has no `Host` header.
+    pub fn host(&self) -> Option<String> {
+        self.headers
+            .get("Host")
+            .map(|val| host_name(val).to_ascii_lowercase())
+    }
+    /// Fields and files of a `multipart/form-data` body, see **Form**. None if the body isn't multipart.
+    /// Every file part is already streamed to the incoming files directory.
+    pub fn form(&self) -> Option<&Form> {
+        self.form.as_ref()
+    }
+    /// Check if the request has a body, held in memory or streamed to disk.
+    /// A `multipart/form-data` body doesn't count, it's read with `form`.
+    pub fn has_body(&self) -> bool {
+        self.body.is_some() || self.body_location.is_some()
+    }
+    /// The whole body as bytes, whether it was held in memory or streamed to disk.
+    /// Empty if the request has no body, see `has_body`.
+    pub fn body_bytes(&self) -> io::Result<Vec<u8>> {
+        match (&self.body, &self.body_location) {
+            (Some(body), _) => Ok(body.as_bytes().to_vec()),
+            (None, Some(loc)) => fs::read(self.uploads.location(loc)),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+    /// The whole body as text, whether it was held in memory or streamed to disk.
+    /// Returns an error of kind `InvalidData` if it isn't valid UTF-8. Empty if the request has no body.
+    pub fn body_text(&self) -> io::Result<String> {
+        match (&self.body, &self.body_location) {
+            (Some(body), _) => Ok(body.clone()),
+            (None, Some(loc)) => fs::read_to_string(self.uploads.location(loc)),
+            (None, None) => Ok(String::new()),
+        }
+    }
+    /// A reader over the body, whether it was held in memory or streamed to disk,
+    /// so a large upload can be processed without loading it at once. Reads nothing if the request has no body.
+    pub fn body_reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match (&self.body, &self.body_location) {
+            (Some(body), _) => Ok(Box::new(body.as_bytes())),
+            (None, Some(loc)) => Ok(Box::new(BufReader::new(File::open(self.uploads.location(loc))?))),
+            (None, None) => Ok(Box::new(io::empty())),
+        }
+    }
+    /// Build `T` from the fields of an `application/x-www-form-urlencoded` body, i.e. a plain html form post,
+    /// the same way `query` builds it from the query parameters. e.g. `req.form_fields::<QueryParams>()` gives every field.
+    /// Fields of a `multipart/form-data` body are read with `form` instead.
+    pub fn form_fields<T: FromQuery>(&self) -> Result<T, ParamError> {
+        T::from_query(&self.form_fields)
+    }
+    /// Trailer headers sent after a `Transfer-Encoding: chunked` body, e.g. a checksum. Empty for other bodies.
+    pub fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+    /// Files uploaded with a `multipart/form-data` body. Empty if there are none.
+    pub fn files(&self) -> &[FormFile] {
+        match &self.form {
+            Some(form) => form.files(),
+            None => &[],
+        }
+    }
+    /// Move the file the body was streamed to, see `body_location`, to `dest` so it's kept after the response is sent.
+    /// Otherwise it's removed once the response is sent. `dest` is replaced if it exists.
+    /// Fails with `NotFound` if the body isn't on disk or it was persisted already.
+    /// `body_location` keeps naming the temporary file, which no longer exists, the body accessors read `dest` afterwards.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use rastapi::Request::{HttpRequest,PathParams};
+    /// use rastapi::Response::{HttpResponse,create_response};
+    /// use rastapi::utils::ContentType;
+    ///
+    /// fn upload_avatar(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+    ///     let dest=format!("avatars/{}.png",path_params.get("user").map_or("unknown",|u| u.as_str()));
+    ///     match req.persist_upload(&dest){
+    ///         Ok(_)=>create_response("Saved.",201,ContentType::TEXT,false).unwrap(),
+    ///         Err(_)=>create_response("Send a png body.",400,ContentType::TEXT,false).unwrap(),
+    ///     }
+    /// }
+    /// ```
+    pub fn persist_upload(&self, dest: impl AsRef<Path>) -> io::Result<()> {
+        match &self.body_location {
+            Some(loc) => self.uploads.persist(loc, dest.as_ref()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "The body isn't stored on disk.")),
+        }
+    }
+    /// Move a file of a `multipart/form-data` body to `dest`, so it's kept after the response is sent, like `persist_upload`.
+    /// Files that aren't persisted are removed once the response is sent. `file.path` keeps naming the temporary file,
+    /// which no longer exists.
+    pub fn persist_file(&self, file: &FormFile, dest: impl AsRef<Path>) -> io::Result<()> {
+        self.uploads.persist(&file.path, dest.as_ref())
+    }
+    /// Shared state of type `T` as an owned `Arc`. Used by the `State` extractor.
+    pub(crate) fn state_arc<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.app_env
+            .as_ref()?
+            .state
+            .get(&TypeId::of::<T>())?
+            .clone()
+            .downcast::<T>()
+            .ok()
+    }
     /// debug function.
     fn display_req_msg(req: &Vec<u8>) {
         for byte in req {
@@ -88,19 +265,52 @@ impl HttpRequest {
         let mut metadata_str = String::new();
         // Last time a byte was recieved. used in Read Time Out scenarios.
         let mut last_recieved = Instant::now();
+        // Where the line being read starts in metadata_str.
+        let mut line_start = 0;
+        // Length of the request line with it's line break, once it's read.
+        let mut request_line_len: Option<usize> = None;
+        let mut header_count = 0;
         // Read all the metadata in a loop while reading a single line in each iteration.
         // Loop stops when encountered and empty line (/r/n), indicating end of metadata.
+        // Reads are bounded by the metadata limits, so an endless line or header block is never held in memory.
         loop {
-            let _ = match buffer_rdr.read_line(&mut metadata_str) {
+            // +2 for the line breaks of the request line and of the empty line.
+            let max_len = match request_line_len {
+                None => app_env.max_request_line_len + 2,
+                Some(len) => len + app_env.max_header_bytes + 2,
+            };
+            if metadata_str.len() >= max_len {
+                return Err(Self::metadata_too_large(request_line_len.is_none(), &app_env));
+            }
+            let budget = (max_len - metadata_str.len()) as u64;
+            let _ = match (&mut *buffer_rdr).take(budget).read_line(&mut metadata_str) {
                 Ok(0) => {
                     log_info!("Client Disconnected");
                     return Err(ApiError::ClientDisconnected(None));
                 }
-                Ok(n) => {
+                Ok(_n) => {
                     last_recieved = Instant::now();
-                    if n < 3 {
+                    // Either the limit was reached or the rest of the line is yet to come, checked on the next iteration.
+                    if !metadata_str.ends_with('\n') {
+                        continue;
+                    }
+                    let line_len = metadata_str.len() - line_start;
+                    line_start = metadata_str.len();
+                    if line_len < 3 {
                         break;
                     }
+                    if request_line_len.is_none() {
+                        request_line_len = Some(line_len);
+                    } else {
+                        header_count += 1;
+                        if header_count > app_env.max_header_count {
+                            log_info!("Too many request headers.");
+                            return Err(ApiError::HeaderFieldsTooLarge(Some(Cow::Owned(format!(
+                                "Too many request headers. At most {} are allowed",
+                                app_env.max_header_count
+                            )))));
+                        }
+                    }
                 }
                 Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
                     if last_recieved.elapsed() > app_env.read_time_out {
@@ -129,6 +339,7 @@ impl HttpRequest {
         //.....
         // /r/n
         let mut first_line_flag = false;
+        let mut content_type_supported = true;
         for line in metadata_str.lines() {
             if line.trim().is_empty() {
                 break;
@@ -138,19 +349,29 @@ impl HttpRequest {
                 match line.trim().split(' ').collect::<Vec<_>>().as_slice() {
                     [method, resorce, protocol] => {
                         request_obj.method = String::from(*method);
-
-                        if let Some((url_part, query_part)) = resorce.split_once('?') {
-                            request_obj.resource = String::from(url_part);
-                            let mut param_map = HashMap::new();
-                            for query in query_part.split('&') {
-                                if let Some((key, val)) = query.split_once('=') {
-                                    param_map.insert(key.to_string(), val.to_string());
-                                }
-                            }
-                            request_obj.params = Some(param_map);
+                        request_obj.raw_target = String::from(*resorce);
+                        let url_part = if let Some((url_part, query_part)) = resorce.split_once('?') {
+                            request_obj.params = QueryParams::parse(query_part);
+                            url_part
                         } else {
-                            request_obj.resource = String::from(*resorce);
-                        }
+                            resorce
+                        };
+                        // Route on the decoded and normalized path. Segments are decoded one by one, so `%2F` stays inside it's segment.
+                        request_obj.resource = match decode_path(url_part) {
+                            Some(path) => path,
+                            None => {
+                                log_response_4xx!(
+                                    request_obj.method,
+                                    url_part,
+                                    400,
+                                    "Invalid request path."
+                                );
+                                return Err(ApiError::InvalidPath(Some(Cow::Owned(format!(
+                                    "Invalid request path {}",
+                                    url_part
+                                )))));
+                            }
+                        };
                         if let Some(proto) = Protocall::from_str(&*protocol.trim()) {
                             request_obj.protocol = proto;
                         } else {
@@ -165,56 +386,33 @@ impl HttpRequest {
                         ))));
                     }
                 }
-                let route = match unsafe { app_env.routes.as_ref() }
-                    .get_route(&request_obj.resource, None)
-                {
-                    Some(r) => r,
-                    None => {
-                        let error_msg = format!("Resource {} not found", request_obj.resource);
-                        log_response_4xx!(
-                            request_obj.method,
-                            request_obj.resource,
-                            404,
-                            "Resource not found."
-                        );
-                        return Err(ApiError::ResourceNotFound(Some(Cow::Owned(error_msg))));
-                    }
-                };
-                let method = match Method::from_string(request_obj.method.as_str()) {
-                    Some(m) => m,
-                    None => {
-                        let error_msg = format!("Method {} not supported.", request_obj.method);
-                        log_response_4xx!(
-                            request_obj.method,
-                            request_obj.resource,
-                            405,
-                            "Method Not Supported."
-                        );
-                        return Err(ApiError::MethodNotSupported(Some(Cow::Owned(error_msg))));
-                    }
-                };
-                if !route.methods.contains(&method) {
-                    let error_msg = format!("Method {} not allowed.", request_obj.method);
-                    log_response_4xx!(
-                        request_obj.method,
-                        request_obj.resource,
-                        405,
-                        "Method Not Allowed."
-                    );
-                    return Err(ApiError::MethodNotAllowed(Some(Cow::Owned(error_msg))));
-                }
                 first_line_flag = true;
+                continue;
             }
-            // Header parsing and validating.
-            if let Some((key, val)) = line.trim().split_once(": ") {
+            // Header parsing and validating. Header names are case-insensitive.
+            if let Some((key, val)) = line.split_once(':') {
+                // No whitespace is allowed in a header name, nor between it and the colon (RFC 9112 section 5.1).
+                if key.is_empty() || key.contains(|c: char| c.is_whitespace()) {
+                    log_response_4xx!(request_obj.method, request_obj.resource, 400, "Invalid header name.");
+                    return Err(ApiError::InvalidHeader(Some(Cow::Owned(format!(
+                        "Invalid header name {:?}",
+                        key
+                    )))));
+                }
+                let val = val.trim();
                 match key {
-                    "Content-Length" | "content-length" if request_obj.method != String::from("GET") => {
+                    // Any method can have a body, it's framed by Content-Length or Transfer-Encoding (RFC 9110 section 6.4.1).
+                    _ if key.eq_ignore_ascii_case("Content-Length") => {
                         let size = match val.parse::<u64>() {
                             Ok(n) => n,
                             Err(_e) => {
                                 return Err(ApiError::InvalidContentLength(None));
                             }
                         };
+                        // Repeated Content-Length headers must agree.
+                        if request_obj.content_len.is_some_and(|len| len != size) {
+                            return Err(ApiError::InvalidContentLength(None));
+                        }
                         if size > app_env.maximum_pay_load_in_bytes as u64 {
                             let _max_mb = app_env.maximum_pay_load_in_bytes / (1024 * 1024);
                             log_response_4xx!(
@@ -226,160 +424,209 @@ impl HttpRequest {
                             return Err(ApiError::PayloadTooLarge(None));
                         }
                         request_obj.content_len = Some(size);
-                        request_obj
-                            .headers
-                            .insert(String::from(key), String::from(val));
                     }
-                    "Content-Type" | "content-type" if request_obj.method != String::from("GET") => {
-                        let content_type = match ContentType::from_header(val) {
-                            Ok(ct) => ct,
-                            Err(_e) => {
-                                return Err(ApiError::ContentNotSupported(None));
+                    // Only checked once it's known there is a body.
+                    _ if key.eq_ignore_ascii_case("Content-Type") => {
+                        content_type_supported = match ContentType::from_header(val) {
+                            Ok(ct) => {
+                                request_obj.content_type = Some(ct);
+                                true
                             }
+                            Err(_e) => false,
                         };
-                        request_obj.content_type = Some(content_type);
-                        request_obj
-                            .headers
-                            .insert(String::from(key), String::from(val));
                     }
-                    _ => {
-                        request_obj.headers.insert(key.to_string(), val.to_string());
+                    _ if key.eq_ignore_ascii_case("Transfer-Encoding") => {
+                        // Only chunked is decoded, and it must be the last coding.
+                        let codings: Vec<&str> = val.split(',').map(|c| c.trim()).collect();
+                        let supported = codings.len() == 1
+                            && codings[0].eq_ignore_ascii_case("chunked")
+                            && !request_obj.chunked;
+                        if !supported {
+                            log_response_4xx!(
+                                request_obj.method,
+                                request_obj.resource,
+                                501,
+                                "Transfer encoding not implemented."
+                            );
+                            return Err(ApiError::NotImplemented(Some(Cow::Owned(format!(
+                                "Transfer-Encoding {} not implemented.",
+                                val
+                            )))));
+                        }
+                        request_obj.chunked = true;
+                    }
+                    _ => (),
+                }
+                // Repeated headers keep all their values.
+                request_obj.headers.append(key, val);
+            }
+        }
+        // The body length would be ambiguous, e.g. to smuggle a second request in the body.
+        if request_obj.chunked && request_obj.content_len.is_some() {
+            log_response_4xx!(
+                request_obj.method,
+                request_obj.resource,
+                400,
+                "Both Content-Length and Transfer-Encoding."
+            );
+            return Err(ApiError::InvalidHeader(Some(Cow::Borrowed(
+                "Content-Length and Transfer-Encoding can't be sent together.",
+            ))));
+        }
+        // The content type of a request without a body doesn't matter.
+        if request_obj.expects_body() && !content_type_supported {
+            log_response_4xx!(
+                request_obj.method,
+                request_obj.resource,
+                415,
+                "Content type not supported."
+            );
+            return Err(ApiError::ContentNotSupported(None));
+        }
+        Self::route_request(request_obj, &app_env)
+    }
+    /// Check if a body follows the metadata, i.e. a `Content-Length` above 0 or a chunked body.
+    /// Requests without one are accepted for every method, with or without a `Content-Type`.
+    fn expects_body(&self) -> bool {
+        self.chunked || self.content_len.is_some_and(|len| len > 0)
+    }
+    // Error for a request line or headers over their limit.
+    fn metadata_too_large<'a>(request_line: bool, app_env: &AppEnv) -> ApiError<'a> {
+        if request_line {
+            log_info!("Request line too long.");
+            ApiError::URITooLong(None)
+        } else {
+            log_info!("Request headers too large.");
+            ApiError::HeaderFieldsTooLarge(Some(Cow::Owned(format!(
+                "Request headers too large. They must be at most {} bytes",
+                app_env.max_header_bytes
+            ))))
+        }
+    }
+    /// Find the route of the request once it's metadata is parsed, so 308, 404 and 405 are sent before the body is read.
+    /// The routes are picked by the `Host` header, see `RastAPI::mount_host`.
+    fn route_request<'a>(request_obj: &mut HttpRequest, app_env: &AppEnv) -> Result<(), ApiError<'a>> {
+        let routes = app_env.routes.load();
+        let host = request_obj.host();
+        let (router, _host_params) = routes.for_host(host.as_deref());
+        if app_env.trailing_slash != TrailingSlash::Strict
+            && router.get_route(&request_obj.resource, None).is_none()
+        {
+            // Same path with the trailing slash added or removed.
+            let alternative = match request_obj.resource.strip_suffix('/') {
+                Some("") => None,
+                Some(stripped) => Some(stripped.to_string()),
+                None => Some(format!("{}/", request_obj.resource)),
+            };
+            if let Some(alternative) =
+                alternative.filter(|alt| router.get_route(alt, None).is_some())
+            {
+                if app_env.trailing_slash == TrailingSlash::Ignore {
+                    request_obj.resource = alternative;
+                } else {
+                    let mut location = encode_path(&alternative);
+                    if let Some((_path, query)) = request_obj.raw_target.split_once('?') {
+                        location.push('?');
+                        location.push_str(query);
                     }
+                    log_response_3xx_content!(
+                        request_obj.method,
+                        request_obj.resource,
+                        308,
+                        "Redirect"
+                    );
+                    return Err(ApiError::PermanentRedirect(None, location));
                 }
             }
         }
+        // No route but a fallback or a user defined 404 handler, they answer every method.
+        let route = match router.get_route(&request_obj.resource, None) {
+            Some(r) => Some(r),
+            None if router.get_fallback(&request_obj.resource, None).is_some() => None,
+            None if routes.not_found.is_some() => None,
+            None => {
+                let error_msg = format!("Resource {} not found", request_obj.resource);
+                log_response_4xx!(
+                    request_obj.method,
+                    request_obj.resource,
+                    404,
+                    "Resource not found."
+                );
+                return Err(ApiError::ResourceNotFound(Some(Cow::Owned(error_msg))));
+            }
+        };
+        let method = match Method::from_string(request_obj.method.as_str()) {
+            Some(m) => m,
+            None => {
+                let error_msg = format!("Method {} not supported.", request_obj.method);
+                log_response_4xx!(
+                    request_obj.method,
+                    request_obj.resource,
+                    405,
+                    "Method Not Supported."
+                );
+                return Err(ApiError::MethodNotSupported(Some(Cow::Owned(error_msg))));
+            }
+        };
+        // A user defined 405 handler gets called after the whole request is read.
+        request_obj.method_not_allowed = route.is_some_and(|r| !r.allows(&method));
+        let route = route.filter(|_r| routes.method_not_allowed.is_none());
+        if let Some(route) = route.filter(|r| !r.allows(&method)) {
+            let error_msg = format!("Method {} not allowed.", request_obj.method);
+            log_response_4xx!(
+                request_obj.method,
+                request_obj.resource,
+                405,
+                "Method Not Allowed."
+            );
+            return Err(ApiError::MethodNotAllowed(
+                Some(Cow::Owned(error_msg)),
+                route.allow_header(),
+            ));
+        }
+        request_obj.routes = Some(routes);
         Ok(())
     }
-    // Parse File type payload.
+    // Parse File type payload, it's streamed to a temporary file.
     fn read_body_to_file<'a>(
-        buffer_rdr: &mut BufReader<&TcpStream>,
+        body: &mut impl Read,
         request_obj: &mut HttpRequest,
         filetype: FileType,
         incoming_dir:&str
     ) -> Result<(), ApiError<'a>> {
-        let mut file_path = match env::current_dir(){
-            Ok(p)=>p,
-            Err(e)=>{
-                error!("{}",e);
-                return Err(ApiError::CannotWriteDataToDisk(Some(Cow::Borrowed("Failed to get the current directory"))));
-            }
-        };
-        file_path.push(incoming_dir);
-        if !file_path.exists(){
-            match fs::create_dir(&file_path){
-                Ok(_)=>(),
-                Err(e)=>{
-                    error!("{}",e);
-                    return Err(ApiError::CannotWriteDataToDisk(Some(Cow::Borrowed("Failed to create incoming directory."))));
-                }
-            };
-        }
-        let mut file_name: String = rand::thread_rng()
-            .sample_iter(Alphanumeric)
-            .take(7)
-            .map(char::from)
-            .collect();
-        file_name.push_str(filetype.extension());
-        file_path.push(file_name);
-        let dest_file = match File::create(&file_path) {
-            Ok(f) => f,
-            Err(e) => {
-                error!("{}", e);
-                return Err(ApiError::RequestBodyNotRead(None));
-            }
-        };
-        let mut dest_file_buff = BufWriter::new(dest_file);
-        let chunk_size = BODY_READ_SPEED;
-        let content_len = match request_obj.content_len {
-            Some(n) => n,
-            None => 0 as u64,
-        };
-        let mut bytes_left_to_read = content_len;
-        while bytes_left_to_read > 0 {
-            let temp_buf_size = min(bytes_left_to_read, chunk_size);
-            let mut temp_buf = vec![0 as u8; temp_buf_size as usize];
-            let _ = match buffer_rdr.read_exact(&mut temp_buf) {
-                Ok(_) => {
-                    match dest_file_buff.write_all(&mut temp_buf) {
-                        Ok(_) => (),
-                        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
-                            error!(
-                                "You don't have permission to write to disk. file location : {:?}",
-                                &file_path
-                            );
-                            return Err(ApiError::CannotWriteDataToDisk(None));
-                        }
-                        Err(e) => {
-                            error!("{}", e);
-                            return Err(ApiError::CannotWriteDataToDisk(None));
-                        }
-                    }
-                    match dest_file_buff.flush() {
-                        Ok(_) => (),
-                        Err(e) => {
-                            error!("{}", e);
-                            return Err(ApiError::CannotWriteDataToDisk(None));
-                        }
-                    }
-                    bytes_left_to_read -= temp_buf_size;
-                }
-                Err(e)
-                    if e.kind() == io::ErrorKind::WouldBlock
-                        || e.kind() == io::ErrorKind::TimedOut =>
-                {
-                    error!("Read time out reached.\n{}", e);
-                    return Err(ApiError::RequestTimedout(None));
-                }
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                    error!("UnexpectedEOF : Connection may be dropped prematurely.");
-                    return Err(ApiError::ClientDisconnected(None));
-                }
-                Err(e) => {
-                    error!("{}", e);
-                    return Err(ApiError::RequestBodyNotRead(None));
-                }
+        let mut dest_file = request_obj.uploads.create(incoming_dir, filetype.extension())?;
+        let mut temp_buf = vec![0 as u8; BODY_READ_SPEED as usize];
+        loop {
+            let n = match body.read(&mut temp_buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // The partial file is removed when dropped.
+                Err(e) => return Err(body_error(e)),
             };
+            dest_file.write(&temp_buf[..n])?;
         }
+        let (file_path, _size) = request_obj.uploads.keep(dest_file)?;
         request_obj.body_location = Some(file_path);
         Ok(())
     }
     // Parse JSON/ Text based payload.
     fn parse_text<'a>(
-        buffer_rdr: &mut BufReader<&TcpStream>,
+        body: &mut impl Read,
         request_obj: &mut HttpRequest,
     ) -> Result<(), ApiError<'a>> {
         let mut final_buf: Vec<u8> = Vec::new();
-        let chunk_size: u64 = BODY_READ_SPEED;
-        let content_len = match request_obj.content_len {
-            Some(n) => n,
-            None => 0 as u64,
+        match body.read_to_end(&mut final_buf) {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(body_error(e));
+            }
         };
-        let mut bytes_left_to_read = content_len;
-        while bytes_left_to_read > 0 {
-            let temp_buf_len = min(chunk_size, bytes_left_to_read);
-            let mut temp_buf = vec![0 as u8; temp_buf_len as usize];
-            let _ = match buffer_rdr.read_exact(&mut temp_buf) {
-                Ok(_) => {
-                    final_buf.append(&mut temp_buf);
-                    bytes_left_to_read -= temp_buf_len;
-                }
-                Err(e)
-                    if e.kind() == io::ErrorKind::TimedOut
-                        || e.kind() == io::ErrorKind::WouldBlock =>
-                {
-                    error!("Read timeout reached.\n {}", e);
-                    return Err(ApiError::RequestTimedout(None));
-                }
-                Err(e) => {
-                    error!("{}", e);
-                    return Err(ApiError::RequestBodyNotRead(Some(Cow::Borrowed(
-                        "Couldn't read request body.",
-                    ))));
-                }
-            };
-        }
-        // Self::display_req_msg(&final_buf);
-        let body = match String::from_utf8(final_buf) {
+        Self::set_text_body(request_obj, final_buf)
+    }
+    // Keep a textual payload in memory, if it's valid UTF-8.
+    fn set_text_body<'a>(request_obj: &mut HttpRequest, bytes: Vec<u8>) -> Result<(), ApiError<'a>> {
+        let body = match String::from_utf8(bytes) {
             Ok(s) => s,
             Err(e) => {
                 error!("{}", e);
@@ -389,9 +636,113 @@ impl HttpRequest {
             }
         };
         request_obj.body = Some(body);
-
         Ok(())
     }
+    // Parse JSON/ Text/ YAML payload. It's held in memory up to the in-memory body limit, a larger one is streamed to disk.
+    fn read_textual<'a>(
+        body: &mut impl Read,
+        request_obj: &mut HttpRequest,
+        filetype: FileType,
+        app_env: &AppEnv,
+    ) -> Result<(), ApiError<'a>> {
+        let limit = app_env.in_memory_body_limit;
+        // The length of a sized body is known up front.
+        if request_obj.content_len.is_some_and(|len| len > limit) {
+            return Self::read_body_to_file(body, request_obj, filetype, &app_env.incoming_file_directory);
+        }
+        let mut head: Vec<u8> = Vec::new();
+        match (&mut *body).take(limit.saturating_add(1)).read_to_end(&mut head) {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(body_error(e));
+            }
+        };
+        if head.len() as u64 <= limit {
+            return Self::set_text_body(request_obj, head);
+        }
+        // A chunked body over the limit, what was read so far is written first.
+        Self::read_body_to_file(
+            &mut head.as_slice().chain(body),
+            request_obj,
+            filetype,
+            &app_env.incoming_file_directory,
+        )
+    }
+    // Parse multipart/form-data payload, file parts are streamed to disk.
+    fn parse_form<'a>(
+        body: &mut impl Read,
+        request_obj: &mut HttpRequest,
+        app_env: &AppEnv,
+    ) -> Result<(), ApiError<'a>> {
+        let boundary = request_obj
+            .headers
+            .get("Content-Type")
+            .and_then(|ct| header_param(ct, "boundary"));
+        let result = match boundary {
+            Some(boundary) => {
+                let limits = MultipartLimits {
+                    part: app_env.multipart_max_part_size,
+                    total: app_env.multipart_max_total_size,
+                };
+                parse_multipart(
+                    body,
+                    &boundary,
+                    &app_env.incoming_file_directory,
+                    limits,
+                    &request_obj.uploads,
+                )
+            }
+            None => Err(ApiError::MalformedBody(Some(Cow::Borrowed(
+                "Multipart body without a boundary.",
+            )))),
+        };
+        match result {
+            Ok(form) => {
+                request_obj.form = Some(form);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+    // Read the body with the reader matching it's content type.
+    fn read_body<'a>(
+        body: &mut impl Read,
+        request_obj: &mut HttpRequest,
+        content_type: ContentType,
+        app_env: &AppEnv,
+    ) -> Result<(), ApiError<'a>> {
+        match content_type {
+            textual_content @ (ContentType::TEXT | ContentType::JSON | ContentType::YAML) => {
+                Self::read_textual(body, request_obj, textual_content.to_file_type(), app_env)
+            }
+            // Form fields are always held in memory, the body is limited by the maximum payload size.
+            ContentType::URLENCODED => {
+                Self::parse_text(body, request_obj)?;
+                if let Some(body) = &request_obj.body {
+                    request_obj.form_fields = QueryParams::parse(body);
+                }
+                Ok(())
+            }
+            ContentType::MULTIPART => Self::parse_form(body, request_obj, app_env),
+            bytes_content @ _ => {
+                let filetype = bytes_content.to_file_type();
+                Self::read_body_to_file(body, request_obj, filetype, &app_env.incoming_file_directory)
+            }
+        }
+    }
+    // Send the interim `100 Continue` response of a request with `Expect: 100-continue`.
+    fn send_continue<'a>(mut stream: &TcpStream) -> Result<(), ApiError<'a>> {
+        match stream
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .and_then(|_| stream.flush())
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{}", e);
+                Err(ApiError::ClientDisconnected(None))
+            }
+        }
+    }
     /// Create a HttpRequest object from a TcpStream. i.e. read the data coming from the stream and
     /// build the HttpRequest Object step by step.
     pub(crate) fn from_tcp_stream<'a>(
@@ -400,6 +751,8 @@ impl HttpRequest {
     ) -> Result<HttpRequest, ApiError<'a>> {
         let _ = stream.set_read_timeout(Some(app_env.read_time_out));
         let mut request_obj: HttpRequest = HttpRequest::new();
+        request_obj.app_env = Some(app_env.clone());
+        request_obj.uploads = TempFiles::new(Some(app_env.incoming_quota.clone()));
         request_obj.client = match stream.peer_addr() {
             Ok(addr) => Some(addr),
             Err(e) => {
@@ -414,55 +767,74 @@ impl HttpRequest {
                 return Err(e);
             }
         };
-        if request_obj.method != String::from("GET") {
-            if let Some(ref content_type) = request_obj.content_type {
-                if let Some(content_len) = request_obj.content_len {
-                    match content_type {
-                        textual_content @ (ContentType::TEXT
-                        | ContentType::JSON
-                        | ContentType::YAML) => {
-                            if content_len < TEXTUAL_CONTENT_MEMORY_LIMIT {
-                                match Self::parse_text(&mut buffer_rdr, &mut request_obj) {
-                                    Ok(_) => (),
-                                    Err(e) => {
-                                        return Err(e);
-                                    }
-                                }
-                            } else {
-                                let filetype = textual_content.to_file_type();
-
-                                match Self::read_body_to_file(
-                                    &mut buffer_rdr,
-                                    &mut request_obj,
-                                    filetype,
-                                    &app_env.incoming_file_directory
-                                ) {
-                                    Ok(_) => (),
-                                    Err(e) => {
-                                        return Err(e);
-                                    }
-                                }
-                            }
-                        }
-                        bytes_content @ _ => {
-                            let filetype = bytes_content.to_file_type();
-
-                            match Self::read_body_to_file(
-                                &mut buffer_rdr,
-                                &mut request_obj,
-                                filetype,
-                                &app_env.incoming_file_directory,
-                            ) {
-                                Ok(_) => (),
-                                Err(e) => {
-                                    return Err(e);
-                                }
-                            }
-                        }
-                    }
+        // Only 100-continue is known, any other expectation can't be met.
+        let expects_continue = match request_obj.headers.get("Expect") {
+            Some(expect) if expect.trim().eq_ignore_ascii_case("100-continue") => true,
+            Some(expect) => {
+                log_response_4xx!(
+                    request_obj.method,
+                    request_obj.resource,
+                    417,
+                    "Expectation failed."
+                );
+                return Err(ApiError::ExpectationFailed(Some(Cow::Owned(format!(
+                    "Expectation {} not supported.",
+                    expect
+                )))));
+            }
+            None => false,
+        };
+        // The request gets a 405 anyway, so the client doesn't need to send the body.
+        if expects_continue && request_obj.method_not_allowed {
+            request_obj.body_unread = true;
+            return Ok(request_obj);
+        }
+        if request_obj.expects_body() {
+            // A body must say what it is.
+            let content_type = match request_obj.content_type {
+                Some(ct) => ct,
+                None => {
+                    return Err(ApiError::ContentTypeRequired(None));
                 }
+            };
+            let mut body = if request_obj.chunked {
+                let limit = app_env.maximum_pay_load_in_bytes as u64;
+                BodyReader::Chunked(ChunkedReader::new(&mut buffer_rdr, limit))
             } else {
-                return Err(ApiError::ContentTypeRequired(None));
+                BodyReader::Sized((&mut buffer_rdr).take(request_obj.content_len.unwrap_or(0)))
+            };
+            // The route, method, size and content type are accepted by now, so the client can send the body.
+            if expects_continue && request_obj.protocol == Protocall::HTTP1_1 {
+                Self::send_continue(stream.as_ref())?;
+            }
+            // The body is read for every method, e.g. a GET with a body, so the next request on the connection starts after it.
+            match Self::read_body(&mut body, &mut request_obj, content_type, &app_env) {
+                Ok(_) => (),
+                Err(e) => {
+                    match e {
+                        ApiError::PayloadTooLarge(_) => log_response_4xx!(
+                            request_obj.method,
+                            request_obj.resource,
+                            413,
+                            "Payload too large."
+                        ),
+                        ApiError::MalformedBody(_) => log_response_4xx!(
+                            request_obj.method,
+                            request_obj.resource,
+                            400,
+                            "Malformed body."
+                        ),
+                        _ => (),
+                    }
+                    return Err(e);
+                }
+            }
+            // A chunked body gets the length it had once decoded.
+            if let Some(decoded_len) = body.decoded_len() {
+                request_obj.content_len = Some(decoded_len);
+            }
+            if let Some(trailers) = body.into_trailers() {
+                request_obj.trailers = trailers;
             }
         }
         Ok(request_obj)
@@ -0,0 +1,501 @@
+//! ## Multipart
+//!
+//! Parsing of `multipart/form-data` request bodies, e.g. html forms with file inputs.
+//! The body is read in chunks and every file part is streamed to it's own temporary file inside the incoming files directory,
+//! so an upload never has to fit in memory. Text fields are kept in memory.
+use super::{
+    body::body_error,
+    uploads::{TempFile, TempFiles},
+};
+use crate::utils::{error::ApiError, percent_decode, ContentType, HeaderMap};
+use std::{
+    borrow::Cow,
+    io::{self, Read},
+    path::PathBuf,
+};
+/// Maximum size of a text field, they are held in memory.
+const FIELD_MEMORY_LIMIT: u64 = 1024 * 1024; //1MB
+/// Maximum size of the headers of a single part.
+const PART_HEADERS_LIMIT: usize = 8192;
+/// Chunk size of bytes read per iteration.
+const READ_CHUNK_SIZE: usize = 8192;
+
+/// ## Form
+/// A parsed `multipart/form-data` body, see `HttpRequest::form`.
+/// Text fields keep the order they were sent in, a field can be sent more than once, e.g. a group of checkboxes.
+///
+/// ## Example
+/// ```no_run
+/// use rastapi::Request::{HttpRequest,PathParams};
+/// use rastapi::Response::{HttpResponse,create_response};
+/// use rastapi::utils::ContentType;
+///
+/// fn upload(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+///     let form=match req.form(){
+///         Some(form)=>form,
+///         None=>return create_response("Send a multipart form.",400,ContentType::TEXT,false).unwrap(),
+///     };
+///     let title=form.get("title").unwrap_or("untitled");
+///     let mut msg=format!("{} :",title);
+///     for file in form.files(){
+///         msg.push_str(&format!(" {:?} ({} bytes) at {:?}",file.file_name,file.size,file.path));
+///     }
+///     create_response(&msg,200,ContentType::TEXT,false).unwrap()
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Form {
+    /// Name value pairs of the text fields, in the order they were sent.
+    fields: Vec<(String, String)>,
+    files: Vec<FormFile>,
+}
+impl Form {
+    /// First value of the text field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, val)| val.as_str())
+    }
+    /// Every value of the text field `name`, in the order they were sent.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, val)| val.as_str())
+            .collect()
+    }
+    /// Iterate over the name value pairs of the text fields.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+    /// First file uploaded with the field `name`.
+    pub fn file(&self, name: &str) -> Option<&FormFile> {
+        self.files.iter().find(|f| f.field == name)
+    }
+    /// Every uploaded file, in the order they were sent.
+    pub fn files(&self) -> &[FormFile] {
+        &self.files
+    }
+}
+/// A file part of a multipart body, streamed to disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormFile {
+    /// Name of the form field.
+    pub field: String,
+    /// Original name of the file as sent by the client, without any directories. None if it was empty.
+    /// Don't use it as a path without checking it.
+    pub file_name: Option<String>,
+    /// Content type of the part as sent by the client, e.g. `image/png`.
+    pub content_type: Option<String>,
+    /// Location of the file inside the incoming files directory. It's a temporary file, removed once the response is sent.
+    /// Keep it with `HttpRequest::persist_file`.
+    pub path: PathBuf,
+    /// Size of the file in bytes.
+    pub size: u64,
+}
+/// Size limits of a multipart body, in bytes.
+#[derive(Clone, Copy)]
+pub(crate) struct MultipartLimits {
+    /// Maximum size of a single part.
+    pub part: u64,
+    /// Maximum size of the whole body.
+    pub total: u64,
+}
+/// Where the content of the current part goes.
+enum Sink {
+    Field(Vec<u8>),
+    File(TempFile),
+}
+/// Value of the parameter `name` of a header, e.g. `boundary` in `multipart/form-data; boundary=xyz`.
+pub(crate) fn header_param(header: &str, name: &str) -> Option<String> {
+    parse_header_params(header)
+        .into_iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, val)| val)
+}
+/// Split the parameters of a header value, e.g. `form-data; name="a;b"; filename=c.txt`.
+/// Quoted values can contain `;` and backslash escapes. The value before the first `;` is skipped.
+fn parse_header_params(header: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = header.chars().peekable();
+    // Skip the value itself.
+    for c in chars.by_ref() {
+        if c == ';' {
+            break;
+        }
+    }
+    loop {
+        let mut key = String::new();
+        for c in chars.by_ref() {
+            if c == '=' || c == ';' {
+                break;
+            }
+            key.push(c);
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut val = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => val.extend(chars.next()),
+                    _ => val.push(c),
+                }
+            }
+        }
+        for c in chars.by_ref() {
+            if c == ';' {
+                break;
+            }
+            val.push(c);
+        }
+        let key = key.trim();
+        if !key.is_empty() {
+            params.push((key.to_ascii_lowercase(), val.trim().to_string()));
+        }
+        if chars.peek().is_none() {
+            return params;
+        }
+    }
+}
+/// File name of a part. `filename*=UTF-8''...` wins over `filename`, directories sent by some clients are removed.
+fn file_name(params: &[(String, String)]) -> Option<Option<String>> {
+    let extended = params
+        .iter()
+        .find(|(key, _)| key == "filename*")
+        .and_then(|(_, val)| val.split_once("''"))
+        .and_then(|(_charset, encoded)| percent_decode(encoded));
+    let name = match extended {
+        Some(name) => name,
+        None => params.iter().find(|(key, _)| key == "filename")?.1.clone(),
+    };
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("").trim();
+    Some(Some(name.to_string()).filter(|n| !n.is_empty()))
+}
+/// Extension of the file on disk. The one of the original name if it's a plain one, else the one of the content type.
+fn file_extension(file_name: Option<&str>, content_type: Option<&str>) -> String {
+    let original = file_name
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext)
+        .filter(|ext| !ext.is_empty() && ext.len() <= 10 && ext.bytes().all(|b| b.is_ascii_alphanumeric()));
+    match original {
+        Some(ext) => format!(".{}", ext.to_ascii_lowercase()),
+        None => content_type
+            .and_then(|ct| ContentType::from_header(ct).ok())
+            .map(|ct| ct.to_file_type().extension().to_string())
+            .unwrap_or_default(),
+    }
+}
+fn malformed(msg: &str) -> ApiError<'static> {
+    ApiError::MalformedBody(Some(Cow::Owned(format!("Malformed multipart body. {}", msg))))
+}
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+/// Reads the body in chunks and keeps the bytes that weren't consumed yet.
+struct PartReader<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    eof: bool,
+    /// Bytes of the body read so far.
+    total: u64,
+    limits: MultipartLimits,
+}
+impl<R: Read> PartReader<R> {
+    /// Read the next chunk. Returns false at the end of the body.
+    fn fill(&mut self) -> Result<bool, ApiError<'static>> {
+        if self.eof {
+            return Ok(false);
+        }
+        let mut chunk = [0_u8; READ_CHUNK_SIZE];
+        let n = loop {
+            match self.reader.read(&mut chunk) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(body_error(e)),
+            }
+        };
+        if n == 0 {
+            self.eof = true;
+            return Ok(false);
+        }
+        self.total += n as u64;
+        if self.total > self.limits.total {
+            return Err(ApiError::PayloadTooLarge(Some(Cow::Owned(format!(
+                "Multipart body too large. Size must be less than {} bytes",
+                self.limits.total
+            )))));
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(true)
+    }
+    /// Discard everything up to and including the first `needle`. While searching only the bytes that could be the start of it
+    /// are kept, so a body without it is never held in memory. Returns false if the body ended first.
+    fn skip_until(&mut self, needle: &[u8]) -> Result<bool, ApiError<'static>> {
+        loop {
+            if let Some(pos) = find(&self.buf, needle) {
+                self.buf.drain(..pos + needle.len());
+                return Ok(true);
+            }
+            let searched = self.buf.len().saturating_sub(needle.len() - 1);
+            self.buf.drain(..searched);
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+    }
+    /// Consume everything up to and including the first `needle`. Returns the consumed bytes without the needle,
+    /// or None if the body ended first.
+    fn read_until(&mut self, needle: &[u8], limit: usize) -> Result<Option<Vec<u8>>, ApiError<'static>> {
+        let mut searched = 0;
+        loop {
+            if let Some(pos) = find(&self.buf[searched..], needle) {
+                let pos = searched + pos;
+                let consumed: Vec<u8> = self.buf.drain(..pos + needle.len()).take(pos).collect();
+                return Ok(Some(consumed));
+            }
+            if self.buf.len() > limit {
+                return Err(malformed("Part headers too large."));
+            }
+            searched = self.buf.len().saturating_sub(needle.len() - 1);
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+}
+/// Parse a multipart body whose parts are separated by `boundary`. File parts are written to temporary files of `uploads`
+/// inside `incoming_dir`. If the body is malformed or too large the file being written is removed,
+/// the ones written before it are removed along with `uploads`.
+pub(crate) fn parse_multipart<R: Read>(
+    reader: R,
+    boundary: &str,
+    incoming_dir: &str,
+    limits: MultipartLimits,
+    uploads: &TempFiles,
+) -> Result<Form, ApiError<'static>> {
+    let mut form = Form::default();
+    if boundary.is_empty() || boundary.len() > 70 {
+        return Err(malformed("Invalid boundary."));
+    }
+    // Every delimiter is preceded by a line break, the first one included once the body starts with one.
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let mut rdr = PartReader {
+        reader,
+        buf: b"\r\n".to_vec(),
+        eof: false,
+        total: 0,
+        limits,
+    };
+    // Anything before the first delimiter is a preamble and is ignored.
+    if !rdr.skip_until(&delimiter)? {
+        return Err(malformed("Missing boundary."));
+    }
+    loop {
+        // Rest of the delimiter line, `--` after the last delimiter.
+        let line = match rdr.read_until(b"\r\n", PART_HEADERS_LIMIT)? {
+            Some(line) => line,
+            // The line break after the closing delimiter is optional.
+            None if rdr.buf.starts_with(b"--") => break,
+            None => return Err(malformed("Missing closing boundary.")),
+        };
+        if line.starts_with(b"--") {
+            break;
+        }
+        // Part headers, up to an empty line.
+        let mut headers = Vec::new();
+        loop {
+            match rdr.read_until(b"\r\n", PART_HEADERS_LIMIT)? {
+                Some(line) if line.is_empty() => break,
+                Some(line) if headers.len() + line.len() <= PART_HEADERS_LIMIT => {
+                    headers.extend_from_slice(&line);
+                    headers.extend_from_slice(b"\r\n");
+                }
+                Some(_line) => return Err(malformed("Part headers too large.")),
+                None => return Err(malformed("Missing part headers.")),
+            }
+        }
+        let headers = parse_part_headers(&headers)?;
+        read_part(&mut rdr, &delimiter, &headers, incoming_dir, uploads, &mut form)?;
+    }
+    // Read the epilogue so the connection can be reused.
+    while rdr.fill()? {
+        rdr.buf.clear();
+    }
+    Ok(form)
+}
+fn parse_part_headers(bytes: &[u8]) -> Result<HeaderMap, ApiError<'static>> {
+    let text = std::str::from_utf8(bytes).map_err(|_e| malformed("Part headers are not UTF-8."))?;
+    let mut headers = HeaderMap::new();
+    for line in text.split("\r\n").filter(|l| !l.is_empty()) {
+        match line.split_once(':') {
+            Some((key, val)) => headers.append(key.trim(), val.trim()),
+            None => return Err(malformed("Invalid part header.")),
+        }
+    }
+    Ok(headers)
+}
+/// Stream the content of a part, i.e. everything up to the next delimiter, into a field or a file.
+fn read_part<R: Read>(
+    rdr: &mut PartReader<R>,
+    delimiter: &[u8],
+    headers: &HeaderMap,
+    incoming_dir: &str,
+    uploads: &TempFiles,
+    form: &mut Form,
+) -> Result<(), ApiError<'static>> {
+    let disposition = match headers.get("Content-Disposition") {
+        Some(d) if d.trim().to_ascii_lowercase().starts_with("form-data") => d,
+        _ => return Err(malformed("Part without a form-data Content-Disposition.")),
+    };
+    let params = parse_header_params(disposition);
+    let field = match params.iter().find(|(key, _)| key == "name") {
+        Some((_, name)) => name.clone(),
+        None => return Err(malformed("Part without a name.")),
+    };
+    let content_type = headers.get("Content-Type").map(|ct| ct.to_string());
+    let file_name = file_name(&params);
+    let mut sink = match &file_name {
+        Some(file_name) => {
+            let extension = file_extension(file_name.as_deref(), content_type.as_deref());
+            // Removed when dropped, if the body turns out to be invalid.
+            Sink::File(uploads.create(incoming_dir, &extension)?)
+        }
+        None => Sink::Field(Vec::new()),
+    };
+    let mut size: u64 = 0;
+    loop {
+        let (end, found) = match find(&rdr.buf, delimiter) {
+            Some(pos) => (pos, true),
+            // Keep the bytes that could be the start of a delimiter.
+            None => (rdr.buf.len().saturating_sub(delimiter.len() - 1), false),
+        };
+        size += end as u64;
+        if size > rdr.limits.part {
+            return Err(ApiError::PayloadTooLarge(Some(Cow::Owned(format!(
+                "Form part {} too large. Size must be less than {} bytes",
+                field, rdr.limits.part
+            )))));
+        }
+        match &mut sink {
+            Sink::Field(value) => {
+                if size > FIELD_MEMORY_LIMIT {
+                    return Err(ApiError::PayloadTooLarge(Some(Cow::Owned(format!(
+                        "Form field {} too large. Size must be less than {} bytes",
+                        field, FIELD_MEMORY_LIMIT
+                    )))));
+                }
+                value.extend_from_slice(&rdr.buf[..end]);
+            }
+            Sink::File(file) => file.write(&rdr.buf[..end])?,
+        }
+        if found {
+            rdr.buf.drain(..end + delimiter.len());
+            break;
+        }
+        rdr.buf.drain(..end);
+        if !rdr.fill()? {
+            return Err(malformed("Missing closing boundary."));
+        }
+    }
+    match sink {
+        Sink::Field(value) => match String::from_utf8(value) {
+            Ok(value) => form.fields.push((field, value)),
+            Err(_e) => return Err(malformed(&format!("Field {} is not UTF-8.", field))),
+        },
+        Sink::File(file) => {
+            let (path, size) = uploads.keep(file)?;
+            form.files.push(FormFile {
+                field,
+                file_name: file_name.flatten(),
+                content_type,
+                path,
+                size,
+            });
+        }
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod multiparttest {
+    use super::*;
+    use std::fs;
+    const LIMITS: MultipartLimits = MultipartLimits {
+        part: 1024,
+        total: 4096,
+    };
+    #[test]
+    fn multipart_test() {
+        let body = "preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Hello--XyZ world\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"tag\"\r\n\r\na\r\n\
+            --XyZ\r\n\
+            content-disposition: form-data; name=\"tag\"\r\n\r\nb\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"doc\"; filename=\"C:\\\\docs\\\\notes.TXT\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line 1\r\nline 2\r\n\
+            --XyZ--\r\nepilogue";
+        let uploads = TempFiles::new(None);
+        let form = parse_multipart(body.as_bytes(), "XyZ", "multipart_test_files", LIMITS, &uploads).unwrap();
+        assert_eq!(form.get("title"), Some("Hello--XyZ world"), "DELIMITER NEEDS IT'S LINE BREAK");
+        assert_eq!(form.get_all("tag"), vec!["a", "b"]);
+        let file = form.file("doc").unwrap();
+        assert_eq!(file.file_name.as_deref(), Some("notes.TXT"));
+        assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(file.size, 14);
+        assert!(file.path.to_string_lossy().ends_with(".txt"));
+        assert_eq!(fs::read_to_string(&file.path).unwrap(), "line 1\r\nline 2");
+
+        let header = "multipart/form-data; charset=utf-8; boundary=\"a;b\"";
+        assert_eq!(header_param(header, "Boundary").as_deref(), Some("a;b"));
+        assert_eq!(
+            file_name(&parse_header_params("form-data; name=f; filename*=UTF-8''na%C3%AFve.png")),
+            Some(Some("naïve.png".to_string()))
+        );
+
+        let unclosed = "--XyZ\r\nContent-Disposition: form-data; name=\"f\"; filename=\"a.bin\"\r\n\r\nabc";
+        match parse_multipart(unclosed.as_bytes(), "XyZ", "multipart_test_files", LIMITS, &uploads) {
+            Err(ApiError::MalformedBody(_)) => (),
+            _ => assert!(false, "UNCLOSED BODY ACCEPTED"),
+        }
+        let big = format!(
+            "--XyZ\r\nContent-Disposition: form-data; name=\"f\"; filename=\"a.bin\"\r\n\r\n{}\r\n--XyZ--",
+            "x".repeat(2000)
+        );
+        match parse_multipart(big.as_bytes(), "XyZ", "multipart_test_files", LIMITS, &uploads) {
+            Err(ApiError::PayloadTooLarge(_)) => (),
+            _ => assert!(false, "PART LIMIT NOT ENFORCED"),
+        }
+        // A body without the boundary is discarded while it's searched, not held in memory.
+        let mut rdr = PartReader {
+            reader: io::repeat(b'x').take(4 * 1024 * 1024),
+            buf: Vec::new(),
+            eof: false,
+            total: 0,
+            limits: MultipartLimits {
+                part: 1024,
+                total: u64::MAX,
+            },
+        };
+        assert!(!rdr.skip_until(b"\r\n--XyZ").unwrap());
+        assert!(rdr.buf.len() < 8, "PREAMBLE KEPT IN MEMORY");
+        let no_boundary = "x".repeat(3000);
+        match parse_multipart(no_boundary.as_bytes(), "XyZ", "multipart_test_files", LIMITS, &uploads) {
+            Err(ApiError::MalformedBody(_)) => (),
+            _ => assert!(false, "BODY WITHOUT BOUNDARY ACCEPTED"),
+        }
+        let leftover = fs::read_dir("multipart_test_files").unwrap().count();
+        assert_eq!(leftover, 1, "FILES OF FAILED BODIES REMOVED");
+        drop(uploads);
+        let leftover = fs::read_dir("multipart_test_files").unwrap().count();
+        assert_eq!(leftover, 0, "FILES REMOVED WITH THE REQUEST");
+        let _ = fs::remove_dir("multipart_test_files");
+    }
+}
@@ -0,0 +1,229 @@
+//! ## Params
+//!
+//! Typed access to url path and query parameters.
+use crate::utils::percent_decode;
+use std::{collections::HashMap, fmt, ops::Deref, str::FromStr};
+
+/// Error returned when a parameter is missing or can't be parsed into the requested type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamError {
+    /// Name of the parameter.
+    pub name: String,
+    /// What went wrong.
+    pub msg: String,
+}
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} : {}", self.name, self.msg)
+    }
+}
+/// Path parameters of a request, e.g. `id` in `/users/{id}`.
+/// It derefs to a `HashMap<String,String>` of the raw values. Use `get_as` to get a parsed value.
+///
+/// ## Example
+/// ```no_run
+/// use rastapi::Request::{HttpRequest,PathParams};
+/// use rastapi::Response::{HttpResponse,create_response};
+/// use rastapi::utils::ContentType;
+/// // Registered as "/users/{id:int}"
+/// fn user(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+///     let id:u64=path_params.get_as("id").unwrap();
+///     create_response(&format!("User {}",id),200,ContentType::TEXT,false).unwrap()
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct PathParams {
+    params: HashMap<String, String>,
+    // Parameter names in the order they appear in the route pattern.
+    order: Vec<String>,
+}
+impl PathParams {
+    /// Create an empty set of path parameters.
+    pub fn new() -> Self {
+        Self {
+            params: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+    /// Get the value of a path parameter parsed into `T`.
+    /// Returns an error if there is no parameter named `name` or if the value can't be parsed.
+    pub fn get_as<T: FromStr>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.params.get(name) {
+            Some(val) => parse_param(name, val),
+            None => Err(ParamError {
+                name: name.to_string(),
+                msg: String::from("missing path parameter"),
+            }),
+        }
+    }
+    pub(crate) fn insert(&mut self, name: &str, val: &str) {
+        if self.params.insert(name.to_string(), val.to_string()).is_none() {
+            self.order.push(name.to_string());
+        }
+    }
+    /// Name and value of the `i`th parameter of the route pattern.
+    pub(crate) fn nth(&self, i: usize) -> Option<(&str, &str)> {
+        let name = self.order.get(i)?;
+        Some((name.as_str(), self.params.get(name)?.as_str()))
+    }
+}
+impl Deref for PathParams {
+    type Target = HashMap<String, String>;
+    fn deref(&self) -> &Self::Target {
+        &self.params
+    }
+}
+impl From<PathParams> for HashMap<String, String> {
+    fn from(params: PathParams) -> Self {
+        params.params
+    }
+}
+/// Query parameters of a request, e.g. `tag` and `page` in `/posts?tag=rust&tag=web&page=2`.
+/// Names and values are percent-decoded and `+` is read as a space. A parameter can appear more than once,
+/// and a parameter without `=`, e.g. `?draft`, is a flag with an empty value.
+///
+/// ## Example
+/// ```no_run
+/// use rastapi::Request::{HttpRequest,PathParams};
+/// use rastapi::Response::{HttpResponse,create_response};
+/// use rastapi::utils::ContentType;
+/// // GET /posts?tag=rust&tag=web&page=2&draft
+/// fn posts(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+///     let tags=req.params.get_all("tag"); // ["rust","web"]
+///     let page:u32=req.params.get_as("page").unwrap_or(1);
+///     let drafts=req.params.flag("draft"); // true
+///     create_response(&format!("{:?} {} {}",tags,page,drafts),200,ContentType::TEXT,false).unwrap()
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QueryParams {
+    /// Name value pairs in the order they appear in the url.
+    pairs: Vec<(String, String)>,
+}
+impl QueryParams {
+    /// Create an empty set of query parameters.
+    pub fn new() -> Self {
+        Self { pairs: Vec::new() }
+    }
+    /// Parse a query string, i.e. the part of the url after `?`.
+    /// A name or value that isn't valid percent-encoding is kept as it is.
+    pub fn parse(query: &str) -> Self {
+        let decode = |s: &str| {
+            let s = s.replace('+', " ");
+            percent_decode(&s).unwrap_or(s)
+        };
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((name, val)) => (decode(name), decode(val)),
+                None => (decode(pair), String::new()),
+            })
+            .collect();
+        Self { pairs }
+    }
+    /// First value of the parameter `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, val)| val.as_str())
+    }
+    /// Every value of the parameter `name`, in the order they appear in the url.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, val)| val.as_str())
+            .collect()
+    }
+    /// Check if the parameter `name` is present, with or without a value.
+    pub fn contains(&self, name: &str) -> bool {
+        self.pairs.iter().any(|(n, _)| n == name)
+    }
+    /// Check if the flag `name` is set, i.e. present without a value, or with any value but `false`, `0`, `no` or `off`.
+    pub fn flag(&self, name: &str) -> bool {
+        match self.get(name) {
+            Some(val) => !["false", "0", "no", "off"]
+                .iter()
+                .any(|f| val.eq_ignore_ascii_case(f)),
+            None => false,
+        }
+    }
+    /// Get the first value of the parameter `name` parsed into `T`.
+    /// Returns an error if there is no parameter named `name` or if the value can't be parsed.
+    pub fn get_as<T: FromStr>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.get(name) {
+            Some(val) => parse_param(name, val),
+            None => Err(ParamError {
+                name: name.to_string(),
+                msg: String::from("missing query parameter"),
+            }),
+        }
+    }
+    /// Get the first value of the parameter `name` parsed into `T`, or None if it's missing.
+    /// Returns an error only if the value can't be parsed.
+    pub fn get_opt_as<T: FromStr>(&self, name: &str) -> Result<Option<T>, ParamError>
+    where
+        T::Err: fmt::Display,
+    {
+        self.get(name).map(|val| parse_param(name, val)).transpose()
+    }
+    /// Get every value of the parameter `name` parsed into `T`. An empty list if it's missing.
+    /// Returns an error if any of the values can't be parsed.
+    pub fn get_all_as<T: FromStr>(&self, name: &str) -> Result<Vec<T>, ParamError>
+    where
+        T::Err: fmt::Display,
+    {
+        self.get_all(name)
+            .into_iter()
+            .map(|val| parse_param(name, val))
+            .collect()
+    }
+    /// Iterate over the name value pairs in the order they appear in the url.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+    /// Total number of name value pairs.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+fn parse_param<T: FromStr>(name: &str, val: &str) -> Result<T, ParamError>
+where
+    T::Err: fmt::Display,
+{
+    val.parse::<T>().map_err(|e| ParamError {
+        name: name.to_string(),
+        msg: format!("invalid value {:?} ({})", val, e),
+    })
+}
+#[cfg(test)]
+mod paramstest {
+    use super::*;
+    #[test]
+    fn query_params_test() {
+        let params = QueryParams::parse("tag=a&tag=b%20c&q=x+y&flag&empty=&&n=5&bad=%zz");
+        assert_eq!(params.get_all("tag"), vec!["a", "b c"]);
+        assert_eq!(params.get("q"), Some("x y"));
+        assert!(params.flag("flag") && params.contains("flag"));
+        assert!(!params.flag("missing"));
+        assert_eq!(params.get("empty"), Some(""));
+        assert_eq!(params.get("bad"), Some("%zz"), "INVALID ENCODING KEPT AS IT IS");
+        assert_eq!(params.get_as::<u32>("n"), Ok(5));
+        assert_eq!(params.get_opt_as::<u32>("page"), Ok(None));
+        assert_eq!(params.get_as::<u32>("q").unwrap_err().name, "q");
+        assert_eq!(params.len(), 7);
+        assert!(QueryParams::parse("").is_empty());
+        assert!(!QueryParams::parse("off=off").flag("off"));
+    }
+}
@@ -0,0 +1,357 @@
+//! ## Uploads
+//!
+//! Lifecycle of the files request bodies are streamed to. Every one of them is a temporary file inside the incoming files directory,
+//! owned by the request it came with and removed once the request is answered, unless the handler keeps it with
+//! `HttpRequest::persist_upload` or `HttpRequest::persist_file`.
+//! Their total size can be limited by a disk quota, see `RastAPI::set_incoming_files_quota`. The quota only counts these
+//! temporary files while their requests are in flight, not the files already in the directory.
+use crate::{error, utils::error::ApiError};
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    borrow::Cow,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+/// Name prefix of temporary files, the startup sweep removes every file starting with it.
+const TEMP_FILE_PREFIX: &str = "upload-";
+/// Length of the random part of a temporary file name.
+const TEMP_NAME_LEN: usize = 16;
+/// Attempts to find a free name before giving up.
+const CREATE_ATTEMPTS: usize = 8;
+/// Length of the random name older versions gave to temporary files.
+const LEGACY_NAME_LEN: usize = 7;
+/// Extensions older versions gave to temporary files, i.e. the ones of `FileType::extension`.
+const LEGACY_EXTENSIONS: [&str; 19] = [
+    ".txt", ".json", ".xml", ".yaml", ".png", ".jpeg", ".svg", ".webp", ".csv", ".xlsx", ".pdf", ".pptx", ".docx", ".mp3",
+    ".wav", ".mp4", ".zip", ".gz", ".exe",
+];
+
+/// Size of the temporary files held in the incoming files directory at once, shared by every request of an app.
+/// Files persisted by handlers or put in the directory otherwise aren't counted.
+pub(crate) struct DiskQuota {
+    /// Maximum size in bytes, None for no limit.
+    limit: Option<u64>,
+    used: AtomicU64,
+}
+impl DiskQuota {
+    pub(crate) fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit,
+            used: AtomicU64::new(0),
+        }
+    }
+    /// Take `n` bytes of the quota. Returns false, without taking anything, if they don't fit.
+    pub(crate) fn reserve(&self, n: u64) -> bool {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                let used = used.checked_add(n)?;
+                match self.limit {
+                    Some(limit) if used > limit => None,
+                    _ => Some(used),
+                }
+            })
+            .is_ok()
+    }
+    /// Give back `n` bytes of the quota.
+    pub(crate) fn release(&self, n: u64) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| Some(used.saturating_sub(n)));
+    }
+    /// Bytes taken so far.
+    pub(crate) fn used(&self) -> u64 {
+        self.used.load(Ordering::SeqCst)
+    }
+}
+/// Absolute path of the incoming files directory, which is created if needed.
+fn incoming_dir_path<'a>(incoming_dir: &str) -> Result<PathBuf, ApiError<'a>> {
+    let mut dir_path = match env::current_dir() {
+        Ok(p) => p,
+        Err(e) => {
+            error!("{}", e);
+            return Err(ApiError::CannotWriteDataToDisk(Some(Cow::Borrowed(
+                "Failed to get the current directory",
+            ))));
+        }
+    };
+    dir_path.push(incoming_dir);
+    if !dir_path.exists() {
+        if let Err(e) = fs::create_dir_all(&dir_path) {
+            error!("{}", e);
+            return Err(ApiError::CannotWriteDataToDisk(Some(Cow::Borrowed(
+                "Failed to create incoming directory.",
+            ))));
+        }
+    }
+    Ok(dir_path)
+}
+/// A temporary file being written. It's removed when dropped, unless it was handed to **TempFiles** with `keep`.
+pub(crate) struct TempFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    /// Bytes written so far, taken from the quota.
+    size: u64,
+    quota: Option<Arc<DiskQuota>>,
+    kept: bool,
+}
+impl TempFile {
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Append `buf` to the file. Fails with `InsufficientStorage` if it doesn't fit in the quota.
+    pub(crate) fn write(&mut self, buf: &[u8]) -> Result<(), ApiError<'static>> {
+        let len = buf.len() as u64;
+        if let Some(quota) = &self.quota {
+            if !quota.reserve(len) {
+                error!("Disk quota of the incoming files directory reached.");
+                return Err(ApiError::InsufficientStorage(None));
+            }
+        }
+        self.size += len;
+        match self.file.write_all(buf) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                error!(
+                    "You don't have permission to write to disk. file location : {:?}",
+                    &self.path
+                );
+                Err(ApiError::CannotWriteDataToDisk(None))
+            }
+            Err(e) => {
+                error!("{}", e);
+                Err(ApiError::CannotWriteDataToDisk(None))
+            }
+        }
+    }
+}
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        let _ = fs::remove_file(&self.path);
+        if let Some(quota) = &self.quota {
+            quota.release(self.size);
+        }
+    }
+}
+/// Temporary files of a request, removed when it's dropped, i.e. after it's response is sent.
+pub(crate) struct TempFiles {
+    /// Path and size of every file still owned by the request.
+    files: Mutex<Vec<(PathBuf, u64)>>,
+    /// Temporary path and destination of every file persisted so far.
+    persisted: Mutex<Vec<(PathBuf, PathBuf)>>,
+    quota: Option<Arc<DiskQuota>>,
+}
+impl TempFiles {
+    pub(crate) fn new(quota: Option<Arc<DiskQuota>>) -> Self {
+        Self {
+            files: Mutex::new(Vec::new()),
+            persisted: Mutex::new(Vec::new()),
+            quota,
+        }
+    }
+    /// Create a new temporary file inside `incoming_dir`, the directory is created if needed.
+    /// The file is created only if the name is free, so two uploads never share a file. Another random name is tried otherwise.
+    pub(crate) fn create(&self, incoming_dir: &str, extension: &str) -> Result<TempFile, ApiError<'static>> {
+        let dir_path = incoming_dir_path(incoming_dir)?;
+        for _ in 0..CREATE_ATTEMPTS {
+            let random: String = rand::thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(TEMP_NAME_LEN)
+                .map(char::from)
+                .collect();
+            let path = dir_path.join(format!("{}{}{}", TEMP_FILE_PREFIX, random, extension));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(TempFile {
+                        path,
+                        file: BufWriter::new(file),
+                        size: 0,
+                        quota: self.quota.clone(),
+                        kept: false,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(ApiError::CannotWriteDataToDisk(None));
+                }
+            }
+        }
+        error!("No free file name found in {:?}", dir_path);
+        Err(ApiError::CannotWriteDataToDisk(None))
+    }
+    /// Flush a fully written file and hand it to the request. Returns it's path and size.
+    pub(crate) fn keep(&self, mut temp_file: TempFile) -> Result<(PathBuf, u64), ApiError<'static>> {
+        if let Err(e) = temp_file.file.flush() {
+            error!("{}", e);
+            return Err(ApiError::CannotWriteDataToDisk(None));
+        }
+        temp_file.kept = true;
+        let kept = (temp_file.path.clone(), temp_file.size);
+        self.lock().push(kept.clone());
+        Ok(kept)
+    }
+    /// Move the temporary file at `path` to `dest`, so it isn't removed with the request.
+    /// Fails with `NotFound` if `path` isn't a temporary file of this request, e.g. it was persisted already.
+    pub(crate) fn persist(&self, path: &Path, dest: &Path) -> io::Result<()> {
+        let mut files = self.lock();
+        let pos = match files.iter().position(|(p, _)| p == path) {
+            Some(pos) => pos,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Not a temporary file of this request.",
+                ))
+            }
+        };
+        // A rename can't cross file systems, the file is copied then.
+        if fs::rename(path, dest).is_err() {
+            fs::copy(path, dest)?;
+            let _ = fs::remove_file(path);
+        }
+        let (path, size) = files.remove(pos);
+        if let Some(quota) = &self.quota {
+            quota.release(size);
+        }
+        self.persisted
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((path, dest.to_path_buf()));
+        Ok(())
+    }
+    /// Where the temporary file at `path` is now, i.e. it's destination if it was persisted, `path` otherwise.
+    pub(crate) fn location(&self, path: &Path) -> PathBuf {
+        self.persisted
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .find(|(p, _)| p == path)
+            .map_or_else(|| path.to_path_buf(), |(_, dest)| dest.clone())
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(PathBuf, u64)>> {
+        self.files.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for (path, size) in self.lock().drain(..) {
+            let _ = fs::remove_file(&path);
+            if let Some(quota) = &self.quota {
+                quota.release(size);
+            }
+        }
+    }
+}
+/// Check if `name` is named like a temporary file of an older version, i.e. 7 random alphanumeric characters
+/// and one of the extensions of `FileType::extension` or none, e.g. `aB3dE5g.png`.
+/// Files a user named the same way, e.g. `avatar1.png`, match too, so they are only removed on request.
+fn is_legacy_name(name: &str) -> bool {
+    let (stem, extension) = name.split_at(name.find('.').unwrap_or(name.len()));
+    stem.len() == LEGACY_NAME_LEN
+        && stem.bytes().all(|b| b.is_ascii_alphanumeric())
+        && (extension.is_empty() || LEGACY_EXTENSIONS.contains(&extension))
+}
+/// What `sweep_incoming_dir` found in the incoming files directory.
+#[derive(Debug, PartialEq)]
+pub(crate) struct SweepReport {
+    /// Files removed.
+    pub(crate) removed: usize,
+    /// Files named like the temporary files of older versions that were left, see `RastAPI::set_remove_legacy_uploads`.
+    pub(crate) legacy: usize,
+    /// Any other file left.
+    pub(crate) left: usize,
+}
+/// Remove the temporary files left in `incoming_dir` by a previous run, e.g. one that crashed while answering a request.
+/// Only files with the temporary file prefix are removed. Files named like the temporary files of older versions
+/// are removed too if `remove_legacy` is true, counted otherwise. Nothing is written to the directory.
+pub(crate) fn sweep_incoming_dir(incoming_dir: &str, remove_legacy: bool) -> io::Result<SweepReport> {
+    let mut report = SweepReport {
+        removed: 0,
+        legacy: 0,
+        left: 0,
+    };
+    let dir_path = env::current_dir()?.join(incoming_dir);
+    let entries = match fs::read_dir(&dir_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(report),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let legacy = is_legacy_name(&name);
+        let is_temp = name.starts_with(TEMP_FILE_PREFIX) || (remove_legacy && legacy);
+        if is_temp && fs::remove_file(entry.path()).is_ok() {
+            report.removed += 1;
+        } else if legacy {
+            report.legacy += 1;
+        } else {
+            report.left += 1;
+        }
+    }
+    Ok(report)
+}
+#[cfg(test)]
+mod uploadstest {
+    use super::*;
+    #[test]
+    fn uploads_test() {
+        let dir = "uploads_test_files";
+        let quota = Arc::new(DiskQuota::new(Some(10)));
+        let uploads = TempFiles::new(Some(quota.clone()));
+        let mut first = uploads.create(dir, ".txt").unwrap();
+        first.write(b"hello").unwrap();
+        let mut second = uploads.create(dir, ".txt").unwrap();
+        assert_ne!(first.path(), second.path());
+        second.write(b"world").unwrap();
+        assert!(matches!(second.write(b"!"), Err(ApiError::InsufficientStorage(_))), "QUOTA NOT ENFORCED");
+        let unfinished = second.path().to_path_buf();
+        drop(second);
+        assert!(!unfinished.exists(), "UNFINISHED FILE KEPT");
+        assert_eq!(quota.used(), 5);
+
+        let (path, size) = uploads.keep(first).unwrap();
+        assert_eq!(size, 5);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+        let mut third = uploads.create(dir, "").unwrap();
+        third.write(b"bye").unwrap();
+        let (third, _) = uploads.keep(third).unwrap();
+        let dest = Path::new(dir).join("kept.txt");
+        uploads.persist(&path, &dest).unwrap();
+        assert_eq!(uploads.location(&path), dest, "PERSISTED FILE NOT FOLLOWED");
+        assert_eq!(quota.used(), 3);
+        assert_eq!(
+            uploads.persist(&path, &dest).unwrap_err().kind(),
+            io::ErrorKind::NotFound,
+            "PERSISTED TWICE"
+        );
+        drop(uploads);
+        assert!(!third.exists(), "TEMP FILE KEPT AFTER THE REQUEST");
+        assert_eq!(quota.used(), 0);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "hello");
+
+        // Files left by a crashed run, the second one by an older version, the last one by a user.
+        fs::write(Path::new(dir).join("upload-orphan.bin"), "x").unwrap();
+        fs::write(Path::new(dir).join("aB3dE5g.png"), "x").unwrap();
+        fs::write(Path::new(dir).join("invoice.html"), "x").unwrap();
+        let report = |removed, legacy, left| SweepReport { removed, legacy, left };
+        assert_eq!(sweep_incoming_dir(dir, false).unwrap(), report(1, 1, 2));
+        assert!(dest.exists(), "SWEEP REMOVED A PERSISTED FILE");
+        assert_eq!(sweep_incoming_dir(dir, true).unwrap(), report(1, 0, 2));
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 2, "SWEEP WROTE TO THE DIRECTORY");
+        assert!(is_legacy_name("Xy12345") && is_legacy_name("Xy12345.gz"));
+        assert!(!is_legacy_name("Xy1234.png") && !is_legacy_name("Xy12345.html") && !is_legacy_name("Xy12345.png.bak"));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -7,14 +7,13 @@ pub(crate) mod pre_built_resp;
 extern crate chrono;
 use crate::cache::{Cache,FileData};
 use crate::error;
-use crate::utils::{ContentType, HTTPCode};
+use crate::utils::{ContentType, Cookie, HTTPCode, HeaderMap};
 use crate::utils::{FileType, Protocall};
 use crate::File::FileWrapper;
 use chrono::format::strftime::StrftimeItems;
 use chrono::offset::Utc;
 use pre_built_resp::{InternalServerError, Notfound404};
 use std::borrow::Cow;
-use std::collections::HashMap;
 use std::fs::File;
 use std::io;
 use std::net::TcpStream;
@@ -38,10 +37,12 @@ pub struct HttpResponse {
     pub file_content_location: Option<PathBuf>,
     /// If response is a file response then it stores the name of the file else it's None.
     pub file_name: Option<String>,
-    /// Response headers.
-    pub headers: HashMap<String, String>,
+    /// Response headers. They are sent in the order they were added.
+    pub headers: HeaderMap,
     /// Flag to determine if we keep the connection alive after response or not.
     pub keep_alive: bool,
+    /// Send only the status line and headers, e.g. for HEAD requests. `Content-Length` still tells the size of the body.
+    pub(crate) head_only: bool,
 }
 impl HttpResponse {
     /// Create a new HttpResponse object.
@@ -55,7 +56,7 @@ impl HttpResponse {
         filename: Option<String>,
         keep_alive: bool,
     ) -> HttpResponse {
-        let headers = HashMap::new();
+        let headers = HeaderMap::new();
 
         HttpResponse {
             code: resp_code,
@@ -67,11 +68,25 @@ impl HttpResponse {
             headers: headers,
             file_name: filename,
             keep_alive: keep_alive,
+            head_only: false,
         }
     }
-    /// Function to add headers.
+    /// Function to add headers. Replaces the value of a header that was already added, use `append_header` to keep both.
     pub fn add_header(&mut self, key: &str, value: &str) {
-        self.headers.insert(String::from(key), String::from(value));
+        self.headers.insert(key, value);
+    }
+    /// Add one more value to a header, e.g. a second `Set-Cookie`. Every value is sent on it's own header line.
+    pub fn append_header(&mut self, key: &str, value: &str) {
+        self.headers.append(key, value);
+    }
+    /// Add a `Set-Cookie` header for `cookie`. Every cookie gets it's own header.
+    pub fn set_cookie(&mut self, cookie: Cookie) {
+        self.headers.append("Set-Cookie", &cookie.to_string());
+    }
+    /// Make the browser delete a cookie, by sending it empty and already expired.
+    /// The path and domain of `cookie` must be the same as when it was set, e.g. `Cookie::named("sid").path("/")`.
+    pub fn remove_cookie(&mut self, cookie: Cookie) {
+        self.set_cookie(cookie.expired());
     }
     fn validate_etag(
         file_len: usize,
@@ -115,7 +130,9 @@ impl HttpResponse {
     ) -> Result<u16, io::Error> {
         if let Some(content) = &self.content {
             let mut resp = self.to_string(protocall);
-            resp.push_str(content);
+            if !self.head_only {
+                resp.push_str(content);
+            }
             let resp_bytes = resp.as_bytes();
             let mut resp_len = resp_bytes.len();
             let mut last_updated = Instant::now();
@@ -189,6 +206,10 @@ impl HttpResponse {
 
                 // Construct metadata of response.
                 let metadata = self.to_string(_protocall);
+                if self.head_only {
+                    Self::send_response_metadata(stream.clone(), &metadata, send_buffer_size, write_time_out)?;
+                    return Ok(self.code as u16);
+                }
 
                 // Check if file in cache or not.
                 #[cfg(feature="caching")]
@@ -44,6 +44,16 @@ pub fn MethodNotSupported(msg: Cow<str>) -> HttpResponse {
     let resp = create_response(&msg, 405, ContentType::TEXT, false).unwrap();
     return resp;
 }
+pub fn AllowedMethods(allow: &str) -> HttpResponse {
+    let mut resp = create_response("", 204, ContentType::TEXT, false).unwrap();
+    resp.add_header("Allow", allow);
+    return resp;
+}
+pub fn PermanentRedirect(location: &str) -> HttpResponse {
+    let mut resp = create_response("", 308, ContentType::TEXT, false).unwrap();
+    resp.add_header("Location", location);
+    return resp;
+}
 pub fn RequestTimeout() -> HttpResponse {
     let resp = create_response("Request timed out", 408, ContentType::TEXT, false).unwrap();
     return resp;
@@ -59,6 +69,30 @@ pub fn PayloadTooLarge(size: usize) -> HttpResponse {
     .unwrap();
     return resp;
 }
+pub fn URITooLong(max_len: usize) -> HttpResponse {
+    let resp = create_response(
+        &format!("Request line too long. It must be at most {} bytes", max_len),
+        414,
+        ContentType::TEXT,
+        false,
+    )
+    .unwrap();
+    return resp;
+}
+pub fn HeaderFieldsTooLarge(msg: &str) -> HttpResponse {
+    let resp = create_response(msg, 431, ContentType::TEXT, false).unwrap();
+    return resp;
+}
+pub fn InsufficientStorage() -> HttpResponse {
+    let resp = create_response(
+        "Not enough storage left for the request body. Try again later.",
+        507,
+        ContentType::TEXT,
+        false,
+    )
+    .unwrap();
+    return resp;
+}
 pub fn UTF8Error() -> HttpResponse {
     let resp = create_response(
         "Request message metadata should be UTF-8 encoding complient.",
@@ -73,6 +107,14 @@ pub fn ReaquestNotHttp(msg: Cow<str>) -> HttpResponse {
     let resp = create_response(&msg, 413, ContentType::TEXT, false).unwrap();
     return resp;
 }
+pub fn BadRequest(msg: &str) -> HttpResponse {
+    let resp = create_response(msg, 400, ContentType::TEXT, false).unwrap();
+    return resp;
+}
+pub fn UnprocessableEntity(msg: &str) -> HttpResponse {
+    let resp = create_response(msg, 422, ContentType::TEXT, false).unwrap();
+    return resp;
+}
 pub fn InternalServerError(msg: &str) -> HttpResponse {
     let resp = create_response(msg, 500, ContentType::TEXT, false).unwrap();
     return resp;
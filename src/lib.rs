@@ -19,19 +19,19 @@
 //!
 //! ```no_run
 //!     use rastapi::RastAPI;
-//!     use rastapi::Request::HttpRequest;
+//!     use rastapi::Request::{HttpRequest,PathParams};
 //!     use rastapi::Response::{HttpResponse,create_response};
 //!     use rastapi::utils::ContentType;
-//!     use std::collections::HashMap;
 //!     
 //!     // Define a Function/Route handler.
 //!     // With this exact fucnction signature.
-//!     // i.e. fn function_name(request_obj:&HttpRequest,path_params:HashMap<String,String>)->HttpResponse
+//!     // i.e. fn function_name(request_obj:&HttpRequest,path_params:PathParams)->HttpResponse
 //!     // request_obj is parameter where all the details of the incoming request is stored.
 //!     // path_params is a parameter where url path params are stored. Not confuse it with url query parameters.
-//!     // query params are stored in request_obj.params.
+//!     // It can be used like a HashMap<String,String> and path_params.get_as::<T>(name) gives a parsed value.
+//!     // query params are stored in request_obj.params, e.g. request_obj.params.get("page") or request_obj.params.get_all("tag").
 //!
-//!     fn json(request_obj:&HttpRequest,path_params:HashMap<String,String>)->HttpResponse{
+//!     fn json(request_obj:&HttpRequest,path_params:PathParams)->HttpResponse{
 //!
 //!         let json_content=r#"{
 //!         "name" : "Rony",
@@ -64,12 +64,11 @@
 //!
 //! ```no_run
 //!     use rastapi::RastAPI;
-//!     use rastapi::Request::HttpRequest;
+//!     use rastapi::Request::{HttpRequest,PathParams};
 //!     use rastapi::Response::{HttpResponse,send_file};
 //!     use rastapi::utils::FileType;
-//!     use std::collections::HashMap;
 //!
-//!     fn file(req:&HttpRequest,path_params:HashMap<String,String>)->HttpResponse{
+//!     fn file(req:&HttpRequest,path_params:PathParams)->HttpResponse{
 //!     let mut resp=send_file("file_path/file_name.ext",Some("file_name.ext".to_string()),FileType::MP4,200,true).unwrap(); // send_file0(file_path,file_name
 //!     resp.add_header("Header-Name","Header-Value");                                              //  file_type,HTTP_CODE,keep_alive_flag)
 //!     return resp;
@@ -84,13 +83,12 @@
 //! ### Load local enviornment variables and access them from std::env module.
 //! ```no_run
 //!     use rastapi::RastAPI;
-//!     use rastapi::Request::HttpRequest;
+//!     use rastapi::Request::{HttpRequest,PathParams};
 //!     use rastapi::Response::{HttpResponse,send_file};
 //!     use rastapi::utils::FileType;
 //!     use rastapi::utils::load_env;
-//!     use std::collections::HashMap;
 //!     use std::env;
-//!     fn file(req:&HttpRequest,path_params:HashMap<String,String>)->HttpResponse{
+//!     fn file(req:&HttpRequest,path_params:PathParams)->HttpResponse{
 //!     let mut resp=send_file("file_path/file_name.ext",Some("file_name.ext".to_string()),FileType::MP4,200,true).unwrap(); // send_file0(file_path,file_name
 //!     resp.add_header("Header-Name","Header-Value");                                              //  file_type,HTTP_CODE,keep_alive_flag)
 //!     return resp;
@@ -104,6 +102,142 @@
 //!         app.run(env::var("HOST").unwrap().as_str(),port);
 //!      }
 //! ```
+//! ## Routing
+//! Routes are stored in a segment based trie which is built once when routes are registered. A route pattern is made of
+//! static segments (`/users`), path parameters (`/{id}`) which match exactly one segment and a trailing wildcard (`/{*rest}` or `/{rest:path}`)
+//! which matches the rest of the url.
+//! A path parameter can have a converter which restricts the values it accepts : `{id:int}`, `{price:float}`, `{key:uuid}` or
+//! a regex like `{slug:[a-z-]+}`. If a value is rejected by the converter, the next matching route is tried, else a 404 is sent.
+//! If more than one pattern can match a url then static segments win over path parameters (constrained ones first) and path parameters
+//! win over wildcards.
+//! Registering a pattern that is already registered, a path parameter with a different name at the same position as an already
+//! registered one, or a constrained path parameter whose converter may accept the same segments as another one at the same position
+//! (e.g. `{id:int}` and `{price:float}`, or two regexes) returns an error, so the matching route never depends on the registration order.
+//! Only `uuid` can share a position with `int` or `float`.
+//! Routes registered with `register_named_route` can be turned back into urls with `url_for`, e.g. for `Location` headers.
+//!
+//! Before routing the path of a request is percent-decoded segment by segment and normalized : repeated slashes are collapsed and `.`/`..` segments
+//! are resolved, a path going above the root gets a `400 Bad Request`. An encoded slash `%2F` stays inside it's segment, so a value
+//! like `a/b` given to `url_for` matches a single path parameter. Path parameters are therefore decoded too, the raw request
+//! target stays available in `HttpRequest::raw_target`. Whether `/users/` matches `/users` is set with `set_trailing_slash_policy`.
+//!
+//! ```no_run
+//!     use rastapi::RastAPI;
+//!     use rastapi::Request::{HttpRequest,PathParams};
+//!     use rastapi::Response::{HttpResponse,create_response};
+//!     use rastapi::utils::ContentType;
+//!     fn user(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+//!         let id:u64=path_params.get_as("id").unwrap(); // "{id:int}" guarantees it's a number.
+//!         create_response(&format!("User {}",id),200,ContentType::TEXT,false).unwrap()
+//!     }
+//!     fn me(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+//!         create_response("me",200,ContentType::TEXT,false).unwrap()
+//!     }
+//!     fn main(){
+//!         let mut app=RastAPI::new();
+//!         app.register_route("/users/{id:int}",vec!["GET"],user).unwrap();
+//!         app.register_route("/users/me",vec!["GET"],me).unwrap(); // "/users/me" always wins over "/users/{id:int}".
+//!         assert!(app.register_route("/users/{user_id:int}",vec!["GET"],user).is_err());
+//!         app.run("127.0.0.1",5000);
+//!     }
+//! ```
+//! ### Closures as route handlers
+//! A route handler can also be a closure. It can capture anything that is `Send + Sync`, e.g. a database pool, configuration or counters.
+//! Arguments of a closure need type annotations, e.g. `|req:&HttpRequest,path_params:PathParams|`.
+//!
+//! ```no_run
+//!     use rastapi::RastAPI;
+//!     use rastapi::Request::{HttpRequest,PathParams};
+//!     use rastapi::Response::create_response;
+//!     use rastapi::utils::ContentType;
+//!     use std::sync::{Arc,atomic::{AtomicUsize,Ordering}};
+//!     fn main(){
+//!         let mut app=RastAPI::new();
+//!         let hits=Arc::new(AtomicUsize::new(0));
+//!         let counter=hits.clone();
+//!         app.register_route("/hits",vec!["GET"],move |_req:&HttpRequest,_path_params:PathParams|{
+//!             let n=counter.fetch_add(1,Ordering::Relaxed)+1;
+//!             create_response(&n.to_string(),200,ContentType::TEXT,false).unwrap()
+//!         }).unwrap();
+//!         app.run("127.0.0.1",5000);
+//!     }
+//! ```
+//! ### Extractors
+//! Instead of `(&HttpRequest,PathParams)` a handler can take up to 8 extractors, typed values pulled out of the request
+//! like `Path<u64>`, `Query<T>`, `Header<N>`, `Body<String>`, `UploadedFile` or `State<T>`.
+//! If an extractor fails the request gets a 400/422 response without calling the handler. See the **Extract** module.
+//!
+//! ```no_run
+//!     use rastapi::RastAPI;
+//!     use rastapi::Extract::Path;
+//!     use rastapi::Response::{HttpResponse,create_response};
+//!     use rastapi::utils::ContentType;
+//!     fn user(Path((team,id)):Path<(String,u64)>)->HttpResponse{
+//!         create_response(&format!("User {} of team {}",id,team),200,ContentType::TEXT,false).unwrap()
+//!     }
+//!     fn main(){
+//!         let mut app=RastAPI::new();
+//!         app.register_route("/teams/{team}/users/{id:int}",vec!["GET"],user).unwrap();
+//!         app.run("127.0.0.1",5000);
+//!     }
+//! ```
+//! ### Sub-routers
+//! Routes can be grouped in a **Router**, e.g. one per module, and mounted under a prefix with `app.mount("/api/v1",router)`.
+//! Routers can be mounted inside other routers. Middleware and the fallback of a router only apply to the routes mounted through it.
+//! See **Router**.
+//!
+//! ### Fallbacks, 404 and 405 handlers
+//! `app.fallback(f)` answers every unmatched url, fallbacks of mounted routers take precedence under their prefix.
+//! `set_not_found_handler` and `set_method_not_allowed_handler` replace the default plain text 404 and 405 responses.
+//!
+//! ```no_run
+//!     use rastapi::{RastAPI, Router};
+//!     use rastapi::Response::create_response;
+//!     use rastapi::utils::ContentType;
+//!     fn main(){
+//!         let mut app=RastAPI::new();
+//!         // JSON API, with it's own 404 shape.
+//!         let mut api=Router::new();
+//!         api.fallback(||create_response(r#"{"error":"not found"}"#,404,ContentType::JSON,false).unwrap());
+//!         app.mount("/api",api).unwrap();
+//!         // Every other url.
+//!         app.fallback(||create_response("Nothing here, try /api",404,ContentType::TEXT,false).unwrap()).unwrap();
+//!         app.set_method_not_allowed_handler(||create_response(r#"{"error":"method not allowed"}"#,405,ContentType::JSON,false).unwrap());
+//!         app.run("127.0.0.1",5000);
+//!     }
+//! ```
+//! ### Virtual hosts
+//! One app can serve several host names with separate routers, picked by the `Host` header of the request,
+//! e.g. `app.mount_host("admin.example.local",admin_router)`. Host patterns can capture a label like `{tenant}.example.local`.
+//! Requests for other hosts use the routes registered on the app. See `RastAPI::mount_host`.
+//!
+//! ### Listing routes
+//! `app.routes()` lists every registered route with it's pattern, methods, name and handler, e.g. to check a deployment
+//! or to generate docs. With `set_log_routes_on_startup(true)` the route table is also logged when the server starts.
+//!
+//! ```no_run
+//!     use rastapi::RastAPI;
+//!     use rastapi::Request::{HttpRequest,PathParams};
+//!     use rastapi::Response::{HttpResponse,create_response};
+//!     use rastapi::utils::ContentType;
+//!     fn user(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+//!         create_response("User",200,ContentType::TEXT,false).unwrap()
+//!     }
+//!     fn main(){
+//!         let mut app=RastAPI::new();
+//!         app.register_named_route("user","/users/{id:int}",vec!["GET","DELETE"],user).unwrap();
+//!         for route in app.routes(){
+//!             println!("{} {:?} {:?}",route.pattern,route.methods(),route.name);
+//!         }
+//!         app.set_log_routes_on_startup(true);
+//!         app.run("127.0.0.1",5000);
+//!     }
+//! ```
+//! ### Changing routes at runtime
+//! `app.router_handle()` gives a cloneable **RouterHandle** that can register, remove or replace routes and mount routers
+//! from any thread while the server is running, e.g. for feature flagged endpoints. Every change is swapped in atomically,
+//! requests already in flight finish with the routes they started with.
+//!
 //! ## Cache
 //! RastAPI uses a slightly tweaked version of a standard LFU-LRU (LFU for eviction and LRU when there is a tie between frequencies of two entity) cache. It only caches files for now.
 //! It's a multi threaded cache So for syncronization we use locks (Mutex). We cann't use RwLock as for LRU-LFU cache as every read qyery is a write query.
@@ -139,6 +273,7 @@
 //! ## Stop the app
 //!  To stop the app gracefully you need to send SIGINT (CTRL + C) or SIGTERM. SIGTSTP(CTRL + Z) is ignored.
 mod App;
+pub mod Extract;
 mod File;
 pub mod Request;
 pub mod Response;
@@ -151,10 +286,14 @@ use libc::{
     SIGTERM, SIGTSTP,getsockopt,SOL_SOCKET,SO_SNDBUF
 };
 use std::{
-    ffi::c_void, io, net::{IpAddr, TcpListener, TcpStream, UdpSocket}, os::fd::AsRawFd, path::PathBuf, process, ptr::NonNull, sync::Arc, time::Duration
+    any::TypeId, ffi::c_void, io, net::{IpAddr, TcpListener, TcpStream, UdpSocket}, os::fd::AsRawFd, path::PathBuf, process, ptr::NonNull, sync::Arc, time::Duration
+};
+use utils::{threadpool::ThreadPool, TrailingSlash};
+use Extract::Handler;
+use App::{
+    client::Client, route_function, AppEnv, AppState,
 };
-use utils::{threadpool::ThreadPool, Method};
-use App::{client::Client, AppEnv, Route, RouteFunction, URLRouter};
+pub use App::{HandlerInfo, Next, RouteInfo, Router, RouterHandle};
 
 // Signal handling ctrl+c & ctrl + z
 static mut SIG_FLAG: bool = false;
@@ -195,13 +334,12 @@ fn get_send_buffer_len(sock_fd:i32)->usize{
 /// ## Example
 /// ```no_run
 /// use rastapi::RastAPI;
-///     use rastapi::Request::HttpRequest;
+///     use rastapi::Request::{HttpRequest,PathParams};
 ///     use rastapi::Response::{HttpResponse,send_file};
 ///     use rastapi::utils::FileType;
 ///     use rastapi::utils::load_env;
-///     use std::collections::HashMap;
 ///
-///     fn route_handler(req:&HttpRequest,path_params:HashMap<String,String>)->HttpResponse{
+///     fn route_handler(req:&HttpRequest,path_params:PathParams)->HttpResponse{
 ///     let mut resp=send_file("file_path/file_name.ext",Some("file_name.ext".to_string()),FileType::MP4,200,true).unwrap(); // send_files(file_path,file_name
 ///     resp.add_header("Header-Name","Header-Value");                                              //  file_type,HTTP_CODE,keep_alive_flag)
 ///     return resp;
@@ -223,7 +361,7 @@ fn get_send_buffer_len(sock_fd:i32)->usize{
 /// ```
 pub struct RastAPI {
     /// Routes of our app.
-    pub(crate) routes: NonNull<URLRouter>,
+    pub(crate) routes: RouterHandle,
     /// Total number of workers. i.e. total threads in our threadpool. It's system threads NOT green threads. Default 10 workers.
     pub total_workers: usize,
     /// Maximum size of payload to accept on each request. If it exceeds for any request our API will automatically send *413 Payload too larrge*. Default 512 MB.
@@ -239,7 +377,30 @@ pub struct RastAPI {
     /// A LFU-LRU cache for file caching. Default size 400 MB, devided among 10 Cache Stores.
     pub(crate) cache: NonNull<Cache<PathBuf>>,
     /// Name of the directory where incoming files are stored. i.e. files coming in request bodies. Default name is `input_files`.
-    pub file_upload_directory_name:String
+    pub file_upload_directory_name:String,
+    /// Maximum length of the request line, e.g. `GET /users?page=2 HTTP/1.1`, in bytes. If it exceeds *414 URI Too Long* is sent. Default 8192 bytes.
+    pub max_request_line_len: usize,
+    /// Maximum size of all the headers of a request together, in bytes. If it exceeds *431 Request Header Fields Too Large* is sent. Default 64 KB.
+    pub max_header_bytes: usize,
+    /// Maximum number of headers of a request. If it exceeds *431 Request Header Fields Too Large* is sent. Default 100 headers.
+    pub max_header_count: usize,
+    /// Textual bodies (text,json,yaml) up to this size are held in memory in `HttpRequest.body`, larger ones are streamed to the disk. Default 1024 KB.
+    pub in_memory_body_limit_in_KB: usize,
+    /// Maximum size of a single part of a `multipart/form-data` body. If it exceeds *413 Payload too large* is sent. Default 100 MB.
+    pub multipart_max_part_size_in_MB: usize,
+    /// Maximum size of a whole `multipart/form-data` body. Default 512 MB.
+    pub multipart_max_total_size_in_MB: usize,
+    /// Maximum size of the temporary files held in the incoming files directory at once, in MB. If it's reached *507 Insufficient Storage* is sent. Default None, i.e. no limit.
+    /// Files already in the directory aren't counted.
+    pub incoming_files_quota_in_MB: Option<usize>,
+    /// Remove the files named like the temporary files of older versions from the incoming files directory at startup. Default false.
+    pub remove_legacy_uploads: bool,
+    /// Shared application state, available to every handler through `HttpRequest::state`.
+    pub(crate) state: AppState,
+    /// What to do with urls that only differ from a route by a trailing slash. Default `TrailingSlash::Strict`.
+    pub trailing_slash: TrailingSlash,
+    /// Log the route table when the server starts. Default false.
+    pub log_routes_on_startup: bool,
 }
 impl RastAPI {
     /// Initializes a RastAPI struct with default configurations.
@@ -249,7 +410,7 @@ impl RastAPI {
         let deafault_write_time_out = Duration::from_secs(5);
         let deafault_keep_alive_time_out = Duration::from_secs(5);
         Self {
-            routes: unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(URLRouter::new()))) },
+            routes: RouterHandle::new(),
             total_workers,
             payload_maximum_size_in_MB: 512 as usize,
             read_time_out: default_read_time_out,
@@ -257,9 +418,49 @@ impl RastAPI {
             keep_alive_time_out: deafault_keep_alive_time_out,
             keep_alive_max_count: 10,
             cache: unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(Cache::new(10, 40)))) },
-            file_upload_directory_name:String::from("input_files")
+            file_upload_directory_name:String::from("input_files"),
+            max_request_line_len: 8192,
+            max_header_bytes: 64 * 1024,
+            max_header_count: 100,
+            in_memory_body_limit_in_KB: 1024,
+            multipart_max_part_size_in_MB: 100,
+            multipart_max_total_size_in_MB: 512,
+            incoming_files_quota_in_MB: None,
+            remove_legacy_uploads: false,
+            state: AppState::new(),
+            trailing_slash: TrailingSlash::Strict,
+            log_routes_on_startup: false,
         }
     }
+    /// Add a shared state to the app. It lives as long as the server and every handler can borrow it with `req.state::<T>()`.
+    /// One value is kept per type, adding a second value of the same type replaces the first one.
+    /// Wrap mutable data in a `Mutex`/`RwLock` or use atomics.
+    /// ## Example
+    /// ```no_run
+    /// use rastapi::RastAPI;
+    /// use rastapi::Request::{HttpRequest,PathParams};
+    /// use rastapi::Response::{HttpResponse,create_response};
+    /// use rastapi::utils::ContentType;
+    /// use std::sync::Mutex;
+    ///
+    /// struct Config{ greeting:String }
+    /// fn hello(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+    ///     let config=req.state::<Config>().unwrap();
+    ///     let visits=req.state::<Mutex<u64>>().unwrap();
+    ///     *visits.lock().unwrap()+=1;
+    ///     create_response(&config.greeting,200,ContentType::TEXT,false).unwrap()
+    /// }
+    /// fn main(){
+    ///     let mut app=RastAPI::new();
+    ///     app.with_state(Config{ greeting:String::from("Hello") });
+    ///     app.with_state(Mutex::new(0_u64));
+    ///     app.register_route("/hello",vec!["GET"],hello).unwrap();
+    ///     app.run("127.0.0.1",5000);
+    /// }
+    /// ```
+    pub fn with_state<T: Send + Sync + 'static>(&mut self, state: T) {
+        self.state.insert(TypeId::of::<T>(), Arc::new(state));
+    }
 
     /// Sets the cache configuration.
     /// ## Parameters
@@ -289,33 +490,165 @@ impl RastAPI {
     /// ## Parameters
     ///   - `url` : The url we want to map.
     ///   - `methods`: Vector of HTTP methods allowed on this route.
-    ///   -`func` : Name of the route handler.
+    ///   -`func` : The route handler. It can be a function or a closure with the signature
+    ///     `Fn(&HttpRequest, PathParams) -> HttpResponse`, or one taking up to 8 extractors (see the **Extract** module)
+    ///     e.g. `Fn(Path<u64>, Query<Paging>, State<Db>) -> HttpResponse`. A closure can capture any `Send + Sync` value, e.g. a DB pool or a counter.
+    ///
+    /// The same url can be registered more than once with different methods and handlers, e.g. one handler for `GET /items`
+    /// and another one for `POST /items`. Requests with any other method get `405 Method Not Allowed` with an `Allow` header.
+    ///
+    /// Methods can be any of GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS, TRACE, CONNECT or an extension method made of
+    /// uppercase letters, e.g. PROPFIND. HEAD is answered by the GET handler with the body stripped, and OPTIONS is answered
+    /// with a `204 No Content` listing the allowed methods, unless they are registered with their own handlers.
     ///
-    /// Can return error if supplied method is not implimented.
+    /// Can return error if supplied method is not implimented, if the url is not a valid route pattern or
+    /// if it conflicts with an already registered route (a method registered twice on the same pattern, or a path parameter
+    /// with a different name at the same position).
     ///
-    pub fn register_route(
+    pub fn register_route<F, Args>(
         &mut self,
         url: &str,
         methods: Vec<&str>,
-        func: RouteFunction,
-    ) -> Result<(), io::Error> {
-        let mut method_list: Vec<Method> = Vec::new();
-        for m in methods {
-            if let Some(_m_) = Method::from_string(m) {
-                method_list.push(_m_);
-            } else {
-                error!(
-                    "No http method named {}. Try using only uppercase letters like GET,POST",
-                    m
-                );
-                return Err(io::ErrorKind::InvalidInput.into());
-            }
-        }
-
-        let route = Route::new(func, method_list);
-        // Routes is not a NULL pointer
-        unsafe { self.routes.as_mut().add_route(url, route) };
-        Ok(())
+        func: F,
+    ) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        self.routes.register_route(url, methods, func)
+    }
+    /// Register a route like `register_route`, under a `name` that can be used to build it's url with `url_for`.
+    /// So links and `Location` headers don't have to hard code paths.
+    ///
+    /// Can return the same errors as `register_route`, or an error if the name is already used by another route.
+    /// ## Example
+    /// ```no_run
+    /// use rastapi::RastAPI;
+    /// use rastapi::Request::{HttpRequest,PathParams};
+    /// use rastapi::Response::{HttpResponse,create_response};
+    /// use rastapi::utils::ContentType;
+    ///
+    /// fn user_detail(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+    ///     create_response(&format!("User {}",path_params["id"]),200,ContentType::TEXT,false).unwrap()
+    /// }
+    /// fn create_user(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+    ///     let mut resp=create_response("Created",201,ContentType::TEXT,false).unwrap();
+    ///     let location=req.url_for("user_detail",&[("id","5")]).unwrap(); // "/users/5"
+    ///     resp.add_header("Location",&location);
+    ///     resp
+    /// }
+    /// fn main(){
+    ///     let mut app=RastAPI::new();
+    ///     app.register_named_route("user_detail","/users/{id:int}",vec!["GET"],user_detail).unwrap();
+    ///     app.register_route("/users",vec!["POST"],create_user).unwrap();
+    ///     app.run("127.0.0.1",5000);
+    /// }
+    /// ```
+    pub fn register_named_route<F, Args>(
+        &mut self,
+        name: &str,
+        url: &str,
+        methods: Vec<&str>,
+        func: F,
+    ) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        self.routes.register_named_route(name, url, methods, func)
+    }
+    /// Build the url of the route registered as `name`, filling it's path parameters from `params`.
+    /// Values are percent-encoded, params that aren't path parameters of the route are added as query parameters.
+    /// Returns None if there is no route named `name` or if a path parameter is missing.
+    /// Inside a handler use `HttpRequest::url_for`.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        self.routes.url_for(name, params)
+    }
+    /// List every registered route, sorted by pattern. Routes of mounted routers are listed with their full pattern.
+    /// The same url registered with different methods is listed once, with a handler per method.
+    pub fn routes(&self) -> impl Iterator<Item = RouteInfo> {
+        self.routes.routes()
+    }
+    /// Get a handle to the routes of this app. It can be cloned and sent to other threads to add, remove or replace
+    /// routes while the server is running, see **RouterHandle**.
+    pub fn router_handle(&self) -> RouterHandle {
+        self.routes.clone()
+    }
+    /// Set a catch-all fallback. It answers every url that doesn't match a route, with any method,
+    /// e.g. to serve the `index.html` of a single page app. Fallbacks of mounted routers take precedence under their prefix.
+    ///
+    /// Returns an error if a fallback is already set on `/`, e.g. by a router mounted there.
+    pub fn fallback<F, Args>(&mut self, func: F) -> Result<(), io::Error>
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        self.routes.add_fallback("/", route_function(func))
+    }
+    /// Set the handler for urls that match neither a route nor a fallback. Replaces the default plain text `404 Not Found`.
+    pub fn set_not_found_handler<F, Args>(&mut self, func: F)
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        let func = route_function(func);
+        self.routes.configure(|router| router.not_found = Some(func));
+    }
+    /// Set the handler for requests with a method the matched route doesn't allow.
+    /// Replaces the default plain text `405 Method Not Allowed`. If the response has no `Allow` header, one listing
+    /// the allowed methods is added.
+    pub fn set_method_not_allowed_handler<F, Args>(&mut self, func: F)
+    where
+        F: Handler<Args>,
+        Args: 'static,
+    {
+        let func = route_function(func);
+        self.routes.configure(|router| router.method_not_allowed = Some(func));
+    }
+    /// Mount a **Router** under `prefix`, e.g. `app.mount("/api/v1",users_router)`.
+    /// Every route of the router gets registered with the prefix joined in front of it,
+    /// with the router's middleware applied and it's fallback answering unmatched urls under the prefix.
+    ///
+    /// Can return the same errors as `register_route`, or an error if a fallback is already set for the prefix.
+    pub fn mount(&mut self, prefix: &str, router: Router) -> Result<(), io::Error> {
+        self.routes.mount(prefix, router)
+    }
+    /// Serve a **Router** on a virtual host, i.e. for requests whose `Host` header matches `host`, e.g. `admin.example.local`.
+    /// A label of the host can be a parameter, e.g. `{tenant}.example.local`, it's value is added to the path parameters
+    /// after the ones of the route. If several patterns match a host the one with fewer parameters wins, then the one with
+    /// a static label at the first position where they differ, whatever the order they were mounted in.
+    /// e.g. `x.example.local` is served by `{tenant}.example.local` rather than `{a}.{b}.local`, and `api.example.local`
+    /// by `api.{env}.local` rather than `{tenant}.example.local`.
+    ///
+    /// Requests for a host that matches no virtual host are routed by the routes registered directly on the app, i.e. the default host.
+    /// The 404 and 405 handlers are shared by all hosts, use the fallback of the router for a host specific 404.
+    /// Mounting more routers on the same host adds their routes to it.
+    ///
+    /// Can return the same errors as `mount`, or an error if the host pattern is malformed or matches the same hosts as another
+    /// pattern, e.g. `{tenant}.example.local` and `{org}.example.local`.
+    /// ## Example
+    /// ```no_run
+    /// use rastapi::{RastAPI, Router};
+    /// use rastapi::Request::{HttpRequest,PathParams};
+    /// use rastapi::Response::{HttpResponse,create_response};
+    /// use rastapi::utils::ContentType;
+    ///
+    /// fn dashboard(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+    ///     create_response(&format!("Dashboard of {}",path_params["tenant"]),200,ContentType::TEXT,false).unwrap()
+    /// }
+    /// fn main(){
+    ///     let mut app=RastAPI::new();
+    ///     let mut tenants=Router::new();
+    ///     tenants.register_route("/dashboard",vec!["GET"],dashboard).unwrap();
+    ///     app.mount_host("{tenant}.example.local",tenants).unwrap(); // GET http://acme.example.local/dashboard
+    ///     let mut admin=Router::new();
+    ///     admin.register_route("/",vec!["GET"],||create_response("Admin",200,ContentType::TEXT,false).unwrap()).unwrap();
+    ///     app.mount_host("admin.example.local",admin).unwrap();
+    ///     app.run("0.0.0.0",5000);
+    /// }
+    /// ```
+    pub fn mount_host(&mut self, host: &str, router: Router) -> Result<(), io::Error> {
+        self.routes.mount_host(host, router)
     }
     // Get the local ipv4 address.
     fn server_wl01_addr() -> Option<String> {
@@ -368,6 +701,64 @@ impl RastAPI {
     pub fn set_incoming_files_directory_name(&mut self,directory_name:&str){
         self.file_upload_directory_name=String::from(directory_name);
     }
+    /// Set the limits of the request metadata: the length of the request line, the total size of the headers in bytes
+    /// and the number of headers. The metadata is never held in memory beyond them.
+    /// A longer request line gets *414 URI Too Long*, too many or too large headers get *431 Request Header Fields Too Large*.
+    pub fn set_metadata_limits(&mut self, max_request_line_len: usize, max_header_bytes: usize, max_header_count: usize) {
+        self.max_request_line_len = max_request_line_len;
+        self.max_header_bytes = max_header_bytes;
+        self.max_header_count = max_header_count;
+    }
+    /// Set the size up to which textual bodies (text,json,yaml) are held in memory, larger ones are streamed to the disk.
+    /// `0` streams every one of them to the disk. Handlers read both the same way with `HttpRequest::body_bytes`,
+    /// `body_text` or `body_reader`.
+    pub fn set_in_memory_body_limit(&mut self, size_in_KB: usize) {
+        self.in_memory_body_limit_in_KB = size_in_KB;
+    }
+    /// Set the maximum size of a single part and of the whole body of `multipart/form-data` requests.
+    /// If one of them is exceeded *413 Payload too large* is sent and the files uploaded so far are removed.
+    /// The whole body is also limited by the maximum payload size.
+    pub fn set_multipart_limits(&mut self, part_size_in_MB: usize, total_size_in_MB: usize) {
+        self.multipart_max_part_size_in_MB = part_size_in_MB;
+        self.multipart_max_total_size_in_MB = total_size_in_MB;
+    }
+    /// Limit the total size of the temporary files held in the incoming files directory at once, i.e. bodies streamed to disk
+    /// whose requests are still being answered. A body that doesn't fit gets *507 Insufficient Storage*.
+    /// No limit by default. Only temporary files of requests in flight are counted, not the size of the directory,
+    /// so files kept with `HttpRequest::persist_upload` or put there otherwise don't count.
+    pub fn set_incoming_files_quota(&mut self, size_in_MB: usize) {
+        self.incoming_files_quota_in_MB = Some(size_in_MB);
+    }
+    /// Remove the files named like the temporary files of versions before `upload-` names, i.e. 7 random alphanumeric
+    /// characters and the extension of a `FileType`, e.g. `aB3dE5g.png`, from the incoming files directory at startup.
+    /// Off by default since files named by users the same way, e.g. `avatar1.png`, would be removed too, they are only counted in the startup log.
+    pub fn set_remove_legacy_uploads(&mut self, remove: bool) {
+        self.remove_legacy_uploads = remove;
+    }
+    /// Set what to do with urls that only differ from a registered route by a trailing slash, e.g. `/users/` for `/users`.
+    /// Default `TrailingSlash::Strict`, i.e. they are different urls.
+    pub fn set_trailing_slash_policy(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+    /// Log the route table, i.e. the methods, pattern, name and handler of every route, when the server starts.
+    pub fn set_log_routes_on_startup(&mut self, log_routes: bool) {
+        self.log_routes_on_startup = log_routes;
+    }
+    // Log the route table.
+    fn log_routes(&self) {
+        let routes: Vec<RouteInfo> = self.routes().collect();
+        log_info!("ROUTES ({})", routes.len());
+        let width = routes.iter().map(|r| r.allow.len()).max().unwrap_or(0);
+        for route in routes {
+            let handlers: Vec<&str> = route.handlers.iter().map(|h| h.handler).collect();
+            let mut line = format!("{:<width$}  {}", route.allow, route.pattern, width = width);
+            if let Some(name) = &route.name {
+                line.push_str(&format!("  ({})", name));
+            }
+            line.push_str(&format!("  -> {}", handlers.join(", ")));
+            log_info!(line);
+        }
+    }
 
     /// Run the application.
     ///
@@ -430,6 +821,32 @@ impl RastAPI {
         log_info!("Listening to {}", &addr);
         log_info!("Press CTRL + C to stop the server.");
         log_info!("Process PID : {}", process::id());
+        if self.log_routes_on_startup {
+            self.log_routes();
+        }
+        // Temporary files of requests that were being answered when a previous run stopped.
+        match Request::sweep_incoming_dir(&app_env.incoming_file_directory, self.remove_legacy_uploads) {
+            Ok(report) => {
+                if report.removed > 0 {
+                    log_info!("Removed {} temporary files left in {}", report.removed, app_env.incoming_file_directory);
+                }
+                if report.legacy > 0 {
+                    log_info!(
+                        "{} files in {} are named like temporary files of older versions, remove them with `set_remove_legacy_uploads`.",
+                        report.legacy,
+                        app_env.incoming_file_directory
+                    );
+                }
+                if report.left > 0 {
+                    log_info!(
+                        "{} other files are left in {}, they are never removed automatically.",
+                        report.left,
+                        app_env.incoming_file_directory
+                    );
+                }
+            }
+            Err(e) => error!("Failed to sweep the incoming files directory. {}", e),
+        }
         unsafe {
             PORT = app_env.port;
         }
@@ -454,16 +871,18 @@ impl RastAPI {
 #[cfg(test)]
 mod apitest {
     use super::*;
-    use io::Read;
+    use io::{Read, Write};
     use reqwest::{
         blocking::Client,
         header::{self, HeaderMap, HeaderValue}
     };
-    use std::{collections::HashMap, fs};
+    use std::fs;
     use std::thread;
-    use utils::{ContentType, FileType};
-    use Request::HttpRequest;
+    use utils::{ContentType, Cookie, FileType, SameSite};
+    use Request::{Form, HttpRequest, ParamError, PathParams, QueryParams};
     use Response::{create_response, send_file, HttpResponse};
+    use Extract::{Body, FromQuery, Header, Path, Query, State};
+    use std::collections::HashMap;
     enum TestResult {
         PASSED,
         FAILED(String),
@@ -545,11 +964,129 @@ mod apitest {
             return resp;
         }
     }
+    fn items_get(_req:&HttpRequest,_path_params:PathParams)->HttpResponse{
+        create_response("GET ITEMS", 200, ContentType::TEXT, false).unwrap()
+    }
+    fn items_post(_req:&HttpRequest,_path_params:PathParams)->HttpResponse{
+        create_response("POST ITEMS", 201, ContentType::TEXT, false).unwrap()
+    }
+    struct Greeting(String);
+    fn greet(req:&HttpRequest,_path_params:PathParams)->HttpResponse{
+        match req.state::<Greeting>(){
+            Some(g)=>create_response(&g.0, 200, ContentType::TEXT, false).unwrap(),
+            None=>create_response("NO STATE", 500, ContentType::TEXT, false).unwrap()
+        }
+    }
+    header_name!(ApiKey, "x-api-key");
+    fn extract(Path(id):Path<u64>,Query(query):Query<HashMap<String,String>>,key:Header<ApiKey>,State(greeting):State<Greeting>)->HttpResponse{
+        let page=query.get("page").map(|p| p.as_str()).unwrap_or("1");
+        let msg=format!("{} {} {} {}",greeting.0,id,page,key.as_str());
+        create_response(&msg, 200, ContentType::TEXT, false).unwrap()
+    }
+    struct Search{ tags:Vec<String>, page:u32, q:String, draft:bool }
+    impl FromQuery for Search{
+        fn from_query(params:&QueryParams)->Result<Self,ParamError>{
+            Ok(Search{
+                tags:params.get_all_as("tag")?,
+                page:params.get_opt_as("page")?.unwrap_or(1),
+                q:params.get_as("q")?,
+                draft:params.flag("draft"),
+            })
+        }
+    }
+    fn search(Query(search):Query<Search>)->HttpResponse{
+        let msg=format!("{:?} {} {} {}",search.tags,search.page,search.q,search.draft);
+        create_response(&msg, 200, ContentType::TEXT, false).unwrap()
+    }
+    fn echo(Body(body):Body<String>)->HttpResponse{
+        create_response(&body, 200, ContentType::TEXT, false).unwrap()
+    }
+    fn api_router()->Router{
+        let mut v1=Router::new();
+        let _ = v1.register_route("/items/{id:int}", vec!["GET"], |Path(id):Path<u64>| create_response(&format!("ITEM {}",id), 200, ContentType::TEXT, false).unwrap()).expect("FAILED TO REGISTER V1 ITEM");
+        v1.middleware(|req:&HttpRequest,path_params:PathParams,next:Next|{
+            let mut resp=next(req,path_params);
+            resp.add_header("X-Api-Version", "1");
+            resp
+        });
+        let mut api=Router::new();
+        api.mount("/v1", v1);
+        api.fallback(||create_response(r#"{"error":"not found"}"#, 404, ContentType::JSON, false).unwrap());
+        api
+    }
+    /// Incoming files directory of the test server, kept out of the repository.
+    fn test_incoming_dir()->PathBuf{
+        std::env::temp_dir().join(format!("rastapi_apitest_{}",process::id()))
+    }
     fn run_server(){
         let mut app = RastAPI::new();
+        app.set_incoming_files_directory_name(&test_incoming_dir().to_string_lossy());
+        app.set_in_memory_body_limit(1);
+        let _ = app.mount("/api", api_router()).expect("FAILED TO MOUNT API");
+        app.set_trailing_slash_policy(TrailingSlash::Redirect);
+        let _ = app.register_named_route("file", "/files/{name}", vec!["GET"], |Path(name):Path<String>| create_response(&name, 200, ContentType::TEXT, false).unwrap()).expect("FAILED TO REGISTER FILES");
+        let _ = app.register_route("/link", vec!["GET"], |req:&HttpRequest,_path_params:PathParams| create_response(&req.url_for("file",&[("name","a b/c")]).unwrap_or_default(), 200, ContentType::TEXT, false).unwrap()).expect("FAILED TO REGISTER LINK");
+        app.set_not_found_handler(|req:&HttpRequest,_path_params:PathParams| create_response(&format!(r#"{{"missing":"{}"}}"#,req.resource), 404, ContentType::JSON, false).unwrap());
+        app.set_method_not_allowed_handler(||create_response("METHOD NOT ALLOWED HERE", 405, ContentType::TEXT, false).unwrap());
+        let _ = app.register_route("/extract/{id:int}", vec!["GET"], extract).expect("FAILED TO REGISTER EXTRACT");
+        let _ = app.register_route("/echo", vec!["POST"], echo).expect("FAILED TO REGISTER ECHO");
+        let _ = app.register_route("/search", vec!["GET"], search).expect("FAILED TO REGISTER SEARCH");
+        let _ = app.register_route("/headers", vec!["GET"], |req:&HttpRequest,_path_params:PathParams|{
+            let mut resp=create_response(&req.headers.get_all("x-tag").join(","), 200, ContentType::TEXT, false).unwrap();
+            resp.append_header("Set-Cookie", "a=1");
+            resp.append_header("Set-Cookie", "b=2");
+            resp
+        }).expect("FAILED TO REGISTER HEADERS");
+        let _ = app.register_route("/cookies", vec!["GET"], |req:&HttpRequest,_path_params:PathParams|{
+            let cookies=req.cookies();
+            let mut resp=create_response(cookies.get("sid").unwrap_or("NO SID"), 200, ContentType::TEXT, false).unwrap();
+            resp.set_cookie(Cookie::new("sid","new").unwrap().http_only().path("/"));
+            resp.set_cookie(Cookie::new("theme","dark").unwrap().same_site(SameSite::Strict));
+            resp.remove_cookie(Cookie::named("old").unwrap().path("/"));
+            resp
+        }).expect("FAILED TO REGISTER COOKIES");
+        app.with_state(Greeting(String::from("HELLO FROM STATE")));
+        let _ = app.register_route("/greet", vec!["GET"], greet).expect("FAILED TO REGISTER GREET");
+        let mut tenants=Router::new();
+        let _ = tenants.register_route("/whoami/{id:int}", vec!["GET"], |_req:&HttpRequest,path_params:PathParams| create_response(&format!("{} {}",path_params["tenant"],path_params["id"]), 200, ContentType::TEXT, false).unwrap()).expect("FAILED TO REGISTER WHOAMI");
+        let _ = app.mount_host("{tenant}.example.local", tenants).expect("FAILED TO MOUNT TENANTS");
+        let mut admin=Router::new();
+        let _ = admin.register_route("/greet", vec!["GET"], ||create_response("HELLO ADMIN", 200, ContentType::TEXT, false).unwrap()).expect("FAILED TO REGISTER ADMIN GREET");
+        let _ = app.mount_host("admin.example.local", admin).expect("FAILED TO MOUNT ADMIN");
+        let _ = app.register_route("/items", vec!["GET"], items_get).expect("FAILED TO REGISTER ITEMS GET");
+        let _ = app.register_route("/items", vec!["POST"], items_post).expect("FAILED TO REGISTER ITEMS POST");
+        let _ = app.register_route("/items", vec!["PROPFIND"], |_req:&HttpRequest,_path_params:PathParams| create_response("PROPFIND ITEMS", 207, ContentType::TEXT, false).unwrap()).expect("FAILED TO REGISTER ITEMS PROPFIND");
         let _ = app.register_route("/json/{id}/{name}", vec!["GET"], json_header_path_params).expect("FAILED TO REGISTER 1");
         let _ = app.register_route("/download", vec!["GET"], file_download).expect("FAILED TO REGISTER 2");
         let _=app.register_route("/upload", vec!["POST"], file_upload);
+        let _ = app.register_route("/form", vec!["POST"], |form:Form|{
+            let file=form.file("doc").unwrap();
+            let content=fs::read_to_string(&file.path).unwrap();
+            let msg=format!("{} {} {:?} {:?} {}",form.get_all("tag").join(","),form.get("title").unwrap_or(""),file.file_name,file.content_type,content);
+            create_response(&msg, 200, ContentType::TEXT, false).unwrap()
+        }).expect("FAILED TO REGISTER FORM");
+        let _ = app.register_route("/signup", vec!["POST"], |req:&HttpRequest,_path_params:PathParams|{
+            let fields=req.form_fields::<QueryParams>().unwrap();
+            let msg=format!("{} {}",fields.get("user").unwrap_or("NO USER"),fields.get_all("role").join(","));
+            create_response(&msg, 200, ContentType::TEXT, false).unwrap()
+        }).expect("FAILED TO REGISTER SIGNUP");
+        let _ = app.register_route("/chunked", vec!["POST"], |req:&HttpRequest,_path_params:PathParams|{
+            let body=req.body_text().unwrap();
+            let mut streamed=String::new();
+            let _ = req.body_reader().unwrap().read_to_string(&mut streamed);
+            if !(streamed==body && req.body_bytes().unwrap()==body.as_bytes()){
+                return create_response("BODY ACCESSORS DIFFER", 500, ContentType::TEXT, false).unwrap();
+            }
+            let body=if body.len()>20{ format!("{}...",&body[..6]) }else{ body };
+            let msg=format!("{} {} {} {}",body,req.content_len.unwrap_or(0),req.trailers().get("x-checksum").unwrap_or("NO TRAILER"),req.body_location.is_some());
+            create_response(&msg, 200, ContentType::TEXT, false).unwrap()
+        }).expect("FAILED TO REGISTER CHUNKED");
+        let _ = app.register_route("/keep", vec!["POST"], |req:&HttpRequest,_path_params:PathParams|{
+            let temp=req.body_location.clone().unwrap_or_default();
+            let kept=req.params.flag("keep") && req.persist_upload(test_incoming_dir().join("kept_upload.bin")).is_ok() && req.body_bytes().is_ok();
+            create_response(&format!("{}|{}",temp.display(),kept), 200, ContentType::TEXT, false).unwrap()
+        }).expect("FAILED TO REGISTER KEEP");
+        let _ = app.register_route("/ping", vec!["GET"], ||create_response("PONG", 200, ContentType::TEXT, true).unwrap()).expect("FAILED TO REGISTER PING");
         app.run("127.0.0.1", 5000);
     }
     #[test]
@@ -707,4 +1244,948 @@ mod apitest {
         }
         
     }
+    #[test]
+    fn routes_listing_test(){
+        fn user(_req:&HttpRequest,_path_params:PathParams)->HttpResponse{
+            create_response("User",200,ContentType::TEXT,false).unwrap()
+        }
+        let mut app=RastAPI::new();
+        app.register_named_route("user","/users/{id:int}",vec!["GET","DELETE"],user).unwrap();
+        let mut v1=Router::new();
+        v1.register_route("/items",vec!["POST"],user).unwrap();
+        v1.middleware(|req:&HttpRequest,path_params:PathParams,next:Next|next(req,path_params));
+        app.mount("/api/v1",v1).unwrap();
+        let routes:Vec<RouteInfo>=app.routes().collect();
+        assert_eq!(routes.len(),2);
+        assert_eq!(routes[0].pattern,"/api/v1/items");
+        assert_eq!(routes[0].methods(),vec!["POST"]);
+        assert!(routes[0].handlers[0].handler.ends_with("::user"),"HANDLER NAME LOST BY MIDDLEWARE : {}",routes[0].handlers[0].handler);
+        assert_eq!(routes[1].pattern,"/users/{id:int}");
+        assert_eq!(routes[1].name.as_deref(),Some("user"));
+        assert_eq!(routes[1].methods(),vec!["GET","DELETE"]);
+    }
+    #[test]
+    fn method_dispatch_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/items").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.text().map(|t| t!="GET ITEMS").unwrap_or(true){
+                return TestResult::FAILED(String::from("WRONG HANDLER FOR GET"));
+            }
+            let resp=match Client::new().post("http://127.0.0.1:5000/items").header("Content-Type","text/plain").body("item").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=201 || resp.text().map(|t| t!="POST ITEMS").unwrap_or(true){
+                return TestResult::FAILED(String::from("WRONG HANDLER FOR POST"));
+            }
+            let resp=match Client::new().put("http://127.0.0.1:5000/items").header("Content-Type","text/plain").body("item").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=405{
+                return TestResult::FAILED(format!("EXPECTED 405 GOT {}",resp.status()));
+            }
+            if !(resp.headers().get("Allow").map(|hv| hv.to_str().unwrap()).eq(&Some("GET, POST, PROPFIND, HEAD, OPTIONS"))){
+                return TestResult::FAILED(String::from("WRONG ALLOW HEADER"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn app_state_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/greet").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=200 || resp.text().map(|t| t!="HELLO FROM STATE").unwrap_or(true){
+                return TestResult::FAILED(String::from("STATE NOT AVAILABLE IN HANDLER"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn extractor_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/extract/7?page=3").header("X-Api-Key","abc").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=200 || resp.text().map(|t| t!="HELLO FROM STATE 7 3 abc").unwrap_or(true){
+                return TestResult::FAILED(String::from("WRONG EXTRACTED VALUES"));
+            }
+            let resp=match Client::new().get("http://127.0.0.1:5000/extract/7").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=400{
+                return TestResult::FAILED(format!("EXPECTED 400 FOR MISSING HEADER GOT {}",resp.status()));
+            }
+            let resp=match Client::new().post("http://127.0.0.1:5000/echo").header("Content-Type","text/plain").body("echo body").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=200 || resp.text().map(|t| t!="echo body").unwrap_or(true){
+                return TestResult::FAILED(String::from("WRONG BODY"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn query_params_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/search?tag=a&tag=b%26c&q=hello+big%20world&draft").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            match (resp.status().as_u16(),resp.text()){
+                (200,Ok(t)) if t==r#"["a", "b&c"] 1 hello big world true"#=>(),
+                (code,t)=>return TestResult::FAILED(format!("WRONG QUERY VALUES {} {:?}",code,t))
+            }
+            let resp=match Client::new().get("http://127.0.0.1:5000/search?q=x&page=two").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=400{
+                return TestResult::FAILED(format!("EXPECTED 400 FOR INVALID PAGE GOT {}",resp.status()));
+            }
+            if !resp.text().map(|t| t.contains("page")).unwrap_or(false){
+                return TestResult::FAILED(String::from("ERROR DOESN'T NAME THE FIELD"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn header_map_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let mut headers=HeaderMap::new();
+            headers.append("x-tag", HeaderValue::from_static("first"));
+            headers.append("X-TAG", HeaderValue::from_static("second"));
+            let resp=match Client::new().get("http://127.0.0.1:5000/headers").headers(headers).send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            let cookies:Vec<&str>=resp.headers().get_all("Set-Cookie").iter().map(|hv| hv.to_str().unwrap()).collect();
+            if cookies!=vec!["a=1","b=2"]{
+                return TestResult::FAILED(format!("WRONG SET-COOKIE HEADERS {:?}",cookies));
+            }
+            match resp.text(){
+                Ok(t) if t=="first,second"=>(),
+                t=>return TestResult::FAILED(format!("REPEATED REQUEST HEADER LOST {:?}",t))
+            }
+            let send=|request:&[u8]|->Result<String,String>{
+                let mut stream=std::net::TcpStream::connect("127.0.0.1:5000").map_err(|e| format!("FAILED TO CONNECT. REASON :\n{}",e))?;
+                let _=stream.write_all(request);
+                let mut resp=String::new();
+                let _=stream.read_to_string(&mut resp);
+                Ok(resp)
+            };
+            // The request line isn't a header, even with a colon in the target.
+            let resp=match send(b"GET /json/12:30/rony HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\n\r\n"){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            if !resp.starts_with("HTTP/1.1 200") || resp.contains("GET /json/12"){
+                return TestResult::FAILED(format!("REQUEST LINE PARSED AS A HEADER {:?}",resp));
+            }
+            for bad in ["X-Bad Name: 1","Host : 127.0.0.1"]{
+                let resp=match send(format!("GET /ping HTTP/1.1\r\n{}\r\nConnection: close\r\n\r\n",bad).as_bytes()){
+                    Ok(r)=>r,
+                    Err(e)=>return TestResult::FAILED(e)
+                };
+                if !resp.starts_with("HTTP/1.1 400"){
+                    return TestResult::FAILED(format!("HEADER NAME WITH WHITESPACE {:?} ACCEPTED",bad));
+                }
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn cookie_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/cookies").header("Cookie","theme=light; sid=abc123").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            let cookies:Vec<&str>=resp.headers().get_all("Set-Cookie").iter().map(|hv| hv.to_str().unwrap()).collect();
+            let expected=vec![
+                "sid=new; Path=/; HttpOnly",
+                "theme=dark; SameSite=Strict",
+                "old=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            ];
+            if cookies!=expected{
+                return TestResult::FAILED(format!("WRONG SET-COOKIE HEADERS {:?}",cookies));
+            }
+            match resp.text(){
+                Ok(t) if t=="abc123"=>(),
+                t=>return TestResult::FAILED(format!("WRONG COOKIE VALUE {:?}",t))
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn multipart_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let body="--b0undary\r\n\
+                Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+                Report\r\n\
+                --b0undary\r\n\
+                Content-Disposition: form-data; name=\"tag\"\r\n\r\nx\r\n\
+                --b0undary\r\n\
+                Content-Disposition: form-data; name=\"tag\"\r\n\r\ny\r\n\
+                --b0undary\r\n\
+                Content-Disposition: form-data; name=\"doc\"; filename=\"report.csv\"\r\n\
+                Content-Type: text/csv\r\n\r\n\
+                a,b\r\n1,2\r\n\
+                --b0undary--\r\n";
+            let resp=match Client::new().post("http://127.0.0.1:5000/form").header("Content-Type","multipart/form-data; boundary=b0undary").body(body).send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            match resp.text(){
+                Ok(t) if t=="x,y Report Some(\"report.csv\") Some(\"text/csv\") a,b\r\n1,2"=>(),
+                t=>return TestResult::FAILED(format!("WRONG FORM {:?}",t))
+            }
+            let resp=match Client::new().post("http://127.0.0.1:5000/form").header("Content-Type","multipart/form-data; boundary=b0undary").body("--b0undary\r\nno headers").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=400{
+                return TestResult::FAILED(format!("MALFORMED BODY GOT {}",resp.status()));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn urlencoded_form_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().post("http://127.0.0.1:5000/signup").header("Content-Type","application/x-www-form-urlencoded; charset=UTF-8").body("user=Jane+Doe&role=admin&role=dev%2Fops").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=200{
+                return TestResult::FAILED(format!("FORM POST GOT {}",resp.status()));
+            }
+            match resp.text(){
+                Ok(t) if t=="Jane Doe admin,dev/ops"=>(),
+                t=>return TestResult::FAILED(format!("WRONG FORM FIELDS {:?}",t))
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn chunked_body_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let send=|request:&[u8]|->Result<String,String>{
+                let mut stream=std::net::TcpStream::connect("127.0.0.1:5000").map_err(|e| format!("FAILED TO CONNECT. REASON :\n{}",e))?;
+                let _=stream.write_all(request);
+                let mut resp=String::new();
+                let _=stream.read_to_string(&mut resp);
+                Ok(resp)
+            };
+            let resp=match send(b"POST /chunked HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n\
+                6\r\nchunky\r\n6;ext=1\r\n bytes\r\n0\r\nX-Checksum: 42\r\n\r\n"){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            if !(resp.starts_with("HTTP/1.1 200") && resp.ends_with("chunky bytes 12 42 false")){
+                return TestResult::FAILED(format!("WRONG CHUNKED RESPONSE {:?}",resp));
+            }
+            // Over the in-memory body limit of 1 KB, so it's streamed to disk.
+            let mut large=b"POST /chunked HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+            for _ in 0..3{
+                large.extend_from_slice(format!("400\r\n{}\r\n","large ".repeat(200).get(..1024).unwrap()).as_bytes());
+            }
+            large.extend_from_slice(b"0\r\n\r\n");
+            let resp=match send(&large){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            if !(resp.starts_with("HTTP/1.1 200") && resp.ends_with("large ... 3072 NO TRAILER true")){
+                return TestResult::FAILED(format!("WRONG LARGE CHUNKED RESPONSE {:?}",resp));
+            }
+            let resp=match send(b"POST /chunked HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            if !resp.starts_with("HTTP/1.1 400"){
+                return TestResult::FAILED(String::from("BOTH CONTENT-LENGTH AND TRANSFER-ENCODING ACCEPTED"));
+            }
+            let resp=match send(b"POST /chunked HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            if !resp.starts_with("HTTP/1.1 400"){
+                return TestResult::FAILED(String::from("INVALID CHUNK SIZE ACCEPTED"));
+            }
+            let resp=match send(b"POST /chunked HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\nTransfer-Encoding: gzip\r\n\r\n"){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            if !resp.starts_with("HTTP/1.1 501"){
+                return TestResult::FAILED(String::from("UNKNOWN TRANSFER CODING ACCEPTED"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn expect_continue_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let connect=||->Result<std::net::TcpStream,String>{
+                let stream=std::net::TcpStream::connect("127.0.0.1:5000").map_err(|e| format!("FAILED TO CONNECT. REASON :\n{}",e))?;
+                // A server that ignores the expectation would leave us waiting here.
+                let _=stream.set_read_timeout(Some(std::time::Duration::from_secs(2)));
+                Ok(stream)
+            };
+            let mut stream=match connect(){
+                Ok(s)=>s,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            let _=stream.write_all(b"POST /echo HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\nContent-Type: text/plain\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n");
+            let mut interim=[0_u8;25];
+            if stream.read_exact(&mut interim).is_err() || &interim!=b"HTTP/1.1 100 Continue\r\n\r\n"{
+                return TestResult::FAILED(String::from("NO 100 CONTINUE"));
+            }
+            let _=stream.write_all(b"hello");
+            let mut resp=String::new();
+            let _=stream.read_to_string(&mut resp);
+            if !(resp.starts_with("HTTP/1.1 200") && resp.ends_with("hello")){
+                return TestResult::FAILED(format!("WRONG RESPONSE AFTER 100 CONTINUE {:?}",resp));
+            }
+            let rejected:[(&[u8],&str);4]=[
+                (b"PUT /items HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n","HTTP/1.1 405"),
+                (b"POST /echo HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\nContent-Length: 999999999999\r\nExpect: 100-continue\r\n\r\n","HTTP/1.1 413"),
+                (b"POST /echo HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/html\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n","HTTP/1.1 415"),
+                (b"POST /echo HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\nContent-Length: 5\r\nExpect: 200-ok\r\n\r\n","HTTP/1.1 417"),
+            ];
+            for (request,status) in rejected{
+                let mut stream=match connect(){
+                    Ok(s)=>s,
+                    Err(e)=>return TestResult::FAILED(e)
+                };
+                let _=stream.write_all(request);
+                let mut resp=String::new();
+                let _=stream.read_to_string(&mut resp);
+                if !resp.starts_with(status){
+                    return TestResult::FAILED(format!("EXPECTED {} WITHOUT SENDING THE BODY, GOT {:?}",status,resp));
+                }
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn body_semantics_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().post("http://127.0.0.1:5000/items").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=201{
+                return TestResult::FAILED(format!("POST WITHOUT BODY GOT {}",resp.status()));
+            }
+            let resp=match Client::new().get("http://127.0.0.1:5000/greet").header("Content-Type","text/html").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=200{
+                return TestResult::FAILED(format!("CONTENT TYPE OF A REQUEST WITHOUT BODY CHECKED, GOT {}",resp.status()));
+            }
+            // The body of a GET is read, so the next request on the connection is parsed from it's own request line.
+            let mut stream=match std::net::TcpStream::connect("127.0.0.1:5000"){
+                Ok(s)=>s,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO CONNECT. REASON :\n{}",e));
+                }
+            };
+            let _=stream.set_read_timeout(Some(std::time::Duration::from_secs(2)));
+            let _=stream.write_all(b"GET /ping HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\nContent-Length: 8\r\n\r\nGET /nop");
+            let mut first=Vec::new();
+            let mut buf=[0_u8;1024];
+            while !String::from_utf8_lossy(&first).ends_with("PONG"){
+                match stream.read(&mut buf){
+                    Ok(n) if n>0=>first.extend_from_slice(&buf[..n]),
+                    _=>return TestResult::FAILED(String::from("NO RESPONSE TO GET WITH A BODY")),
+                }
+            }
+            let _=stream.write_all(b"GET /ping HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\n\r\n");
+            let mut second=String::new();
+            let _=stream.read_to_string(&mut second);
+            if !(second.starts_with("HTTP/1.1 200") && second.ends_with("PONG")){
+                return TestResult::FAILED(format!("SECOND REQUEST ON THE CONNECTION GOT {:?}",second));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn metadata_limits_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let long_url=format!("GET /ping?q={} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n","a".repeat(9000));
+            let many_headers=format!("GET /ping HTTP/1.1\r\nHost: 127.0.0.1\r\n{}\r\n","X-Tag: a\r\n".repeat(100));
+            let large_headers=format!("GET /ping HTTP/1.1\r\nHost: 127.0.0.1\r\nX-Big: {}\r\n\r\n","b".repeat(70*1024));
+            for (request,status) in [(long_url,"HTTP/1.1 414"),(many_headers,"HTTP/1.1 431"),(large_headers,"HTTP/1.1 431")]{
+                let mut stream=match std::net::TcpStream::connect("127.0.0.1:5000"){
+                    Ok(s)=>s,
+                    Err(e)=>{
+                        return TestResult::FAILED(format!("FAILED TO CONNECT. REASON :\n{}",e));
+                    }
+                };
+                let _=stream.set_read_timeout(Some(std::time::Duration::from_secs(2)));
+                let _=stream.write_all(request.as_bytes());
+                let mut resp=Vec::new();
+                let _=stream.read_to_end(&mut resp);
+                if !resp.starts_with(status.as_bytes()){
+                    return TestResult::FAILED(format!("EXPECTED {}, GOT {:?}",status,String::from_utf8_lossy(&resp)));
+                }
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn upload_lifecycle_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let send=|target:&str|->Result<(String,bool),String>{
+                let mut stream=std::net::TcpStream::connect("127.0.0.1:5000").map_err(|e| format!("FAILED TO CONNECT. REASON :\n{}",e))?;
+                let _=stream.write_all(format!("POST {} HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\nContent-Type: image/png\r\nContent-Length: 3\r\n\r\nabc",target).as_bytes());
+                let mut resp=String::new();
+                let _=stream.read_to_string(&mut resp);
+                let body=resp.split_once("\r\n\r\n").map(|(_,body)| body).unwrap_or("");
+                match body.split_once('|'){
+                    Some((temp,kept))=>Ok((temp.to_string(),kept=="true")),
+                    None=>Err(format!("WRONG KEEP RESPONSE {:?}",resp)),
+                }
+            };
+            // The connection is closed after the request is dropped, so it's temporary file is gone by now.
+            let (temp,kept)=match send("/keep"){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            if kept || !temp.contains("upload-") || std::path::Path::new(&temp).exists(){
+                return TestResult::FAILED(format!("TEMPORARY FILE {} NOT REMOVED",temp));
+            }
+            let (temp,kept)=match send("/keep?keep=1"){
+                Ok(r)=>r,
+                Err(e)=>return TestResult::FAILED(e)
+            };
+            let persisted=fs::read_to_string(test_incoming_dir().join("kept_upload.bin"));
+            let _=fs::remove_file(test_incoming_dir().join("kept_upload.bin"));
+            if !kept || std::path::Path::new(&temp).exists() || persisted.ok().as_deref()!=Some("abc"){
+                return TestResult::FAILED(String::from("UPLOAD NOT PERSISTED"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn mount_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/api/v1/items/3").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if !(resp.headers().get("X-Api-Version").map(|hv| hv.to_str().unwrap()).eq(&Some("1"))){
+                return TestResult::FAILED(String::from("MIDDLEWARE NOT APPLIED"));
+            }
+            if resp.status().as_u16()!=200 || resp.text().map(|t| t!="ITEM 3").unwrap_or(true){
+                return TestResult::FAILED(String::from("WRONG MOUNTED ROUTE"));
+            }
+            let resp=match Client::new().get("http://127.0.0.1:5000/api/missing").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=404 || resp.text().map(|t| t!=r#"{"error":"not found"}"#).unwrap_or(true){
+                return TestResult::FAILED(String::from("ROUTER FALLBACK NOT USED"));
+            }
+            let resp=match Client::new().get("http://127.0.0.1:5000/items").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.headers().get("X-Api-Version").is_some(){
+                return TestResult::FAILED(String::from("MIDDLEWARE APPLIED OUTSIDE IT'S ROUTER"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn router_handle_test(){
+        // Own server, so the routes changed here don't leak into the other tests.
+        let (sender,reciever)=std::sync::mpsc::channel();
+        let _handle1=thread::spawn(move ||{
+            let mut app=RastAPI::new();
+            let _ = app.register_route("/stable", vec!["GET"], ||create_response("STABLE", 200, ContentType::TEXT, false).unwrap()).expect("FAILED TO REGISTER STABLE");
+            sender.send(app.router_handle()).expect("FAILED TO SEND HANDLE");
+            app.run("127.0.0.1",5001);
+        });
+        let handle=reciever.recv().expect("FAILED TO RECIEVE HANDLE");
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(move ||{
+            let get=|url:&str|->Result<(u16,String),String>{
+                match Client::new().get(url).send(){
+                    Ok(R)=>Ok((R.status().as_u16(),R.text().unwrap_or_default())),
+                    Err(e)=>Err(format!("FAILED TO SEND REQUEST. REASON :\n{}",e))
+                }
+            };
+            let url="http://127.0.0.1:5001/beta";
+            match get(url){
+                Ok((404,_))=>(),
+                Ok((code,_))=>return TestResult::FAILED(format!("EXPECTED 404 BEFORE REGISTERING GOT {}",code)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            if let Err(e)=handle.register_named_route("beta","/beta",vec!["GET"],||create_response("BETA", 200, ContentType::TEXT, false).unwrap()){
+                return TestResult::FAILED(format!("FAILED TO REGISTER AT RUNTIME : {}",e));
+            }
+            // A snapshot taken before a change keeps the old routes.
+            let snapshot=handle.shared.load();
+            match get(url){
+                Ok((200,body)) if body=="BETA"=>(),
+                Ok((code,body))=>return TestResult::FAILED(format!("EXPECTED 200 BETA GOT {} {}",code,body)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            if handle.replace_route("/beta",vec!["GET"],||create_response("BETA 2", 200, ContentType::TEXT, false).unwrap()).is_err(){
+                return TestResult::FAILED(String::from("FAILED TO REPLACE"));
+            }
+            match get(url){
+                Ok((200,body)) if body=="BETA 2"=>(),
+                Ok((code,body))=>return TestResult::FAILED(format!("EXPECTED 200 BETA 2 GOT {} {}",code,body)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            if handle.url_for("beta",&[]).as_deref()!=Some("/beta"){
+                return TestResult::FAILED(String::from("NAME LOST BY REPLACE"));
+            }
+            if handle.remove_route("/beta").is_err(){
+                return TestResult::FAILED(String::from("FAILED TO REMOVE"));
+            }
+            if handle.remove_route("/beta").map_err(|e| e.kind())!=Err(io::ErrorKind::NotFound){
+                return TestResult::FAILED(String::from("REMOVED A ROUTE TWICE"));
+            }
+            match get(url){
+                Ok((404,_))=>(),
+                Ok((code,_))=>return TestResult::FAILED(format!("EXPECTED 404 AFTER REMOVING GOT {}",code)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            if snapshot.get_route("/beta",None).is_none(){
+                return TestResult::FAILED(String::from("SNAPSHOT CHANGED"));
+            }
+            // A failed mount doesn't add any of it's routes.
+            let mut conflicting=Router::new();
+            let _ = conflicting.register_route("/fresh",vec!["GET"],||create_response("FRESH", 200, ContentType::TEXT, false).unwrap());
+            let _ = conflicting.register_route("/stable",vec!["GET"],||create_response("STABLE 2", 200, ContentType::TEXT, false).unwrap());
+            if handle.mount("/",conflicting).is_ok(){
+                return TestResult::FAILED(String::from("CONFLICTING MOUNT SUCCEEDED"));
+            }
+            match get("http://127.0.0.1:5001/fresh"){
+                Ok((404,_))=>(),
+                Ok((code,_))=>return TestResult::FAILED(format!("PARTIAL MOUNT, GOT {}",code)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            match get("http://127.0.0.1:5001/stable"){
+                Ok((200,body)) if body=="STABLE"=>(),
+                Ok((code,body))=>return TestResult::FAILED(format!("EXPECTED 200 STABLE GOT {} {}",code,body)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn virtual_host_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let get=|url:&str,host:&str|->Result<(u16,String),String>{
+                match Client::new().get(url).header("Host",host).send(){
+                    Ok(R)=>Ok((R.status().as_u16(),R.text().unwrap_or_default())),
+                    Err(e)=>Err(format!("FAILED TO SEND REQUEST. REASON :\n{}",e))
+                }
+            };
+            match get("http://127.0.0.1:5000/greet","admin.example.local:5000"){
+                Ok((200,body)) if body=="HELLO ADMIN"=>(),
+                Ok((code,body))=>return TestResult::FAILED(format!("WRONG ADMIN RESPONSE {} {}",code,body)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            match get("http://127.0.0.1:5000/whoami/7","Acme.Example.Local"){
+                Ok((200,body)) if body=="acme 7"=>(),
+                Ok((code,body))=>return TestResult::FAILED(format!("WRONG TENANT RESPONSE {} {}",code,body)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            // Routes of the default host aren't served on a virtual host.
+            match get("http://127.0.0.1:5000/greet","acme.example.local"){
+                Ok((404,_))=>(),
+                Ok((code,_))=>return TestResult::FAILED(format!("EXPECTED 404 ON TENANT HOST GOT {}",code)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            // Unknown hosts fall back to the default host.
+            match get("http://127.0.0.1:5000/greet","unknown.local"){
+                Ok((200,body)) if body=="HELLO FROM STATE"=>(),
+                Ok((code,body))=>return TestResult::FAILED(format!("WRONG DEFAULT HOST RESPONSE {} {}",code,body)),
+                Err(e)=>return TestResult::FAILED(e)
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn head_options_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().head("http://127.0.0.1:5000/items").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=200{
+                return TestResult::FAILED(format!("EXPECTED 200 FOR HEAD GOT {}",resp.status()));
+            }
+            if !(resp.headers().get("Content-Length").map(|hv| hv.to_str().unwrap()).eq(&Some("9"))){
+                return TestResult::FAILED(String::from("CONTENT LENGTH NOT KEPT FOR HEAD"));
+            }
+            if resp.bytes().map(|b| !b.is_empty()).unwrap_or(true){
+                return TestResult::FAILED(String::from("BODY SENT FOR HEAD"));
+            }
+            let resp=match Client::new().request(reqwest::Method::OPTIONS,"http://127.0.0.1:5000/items").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=204{
+                return TestResult::FAILED(format!("EXPECTED 204 FOR OPTIONS GOT {}",resp.status()));
+            }
+            if !(resp.headers().get("Allow").map(|hv| hv.to_str().unwrap()).eq(&Some("GET, POST, PROPFIND, HEAD, OPTIONS"))){
+                return TestResult::FAILED(String::from("WRONG ALLOW HEADER FOR OPTIONS"));
+            }
+            let propfind=reqwest::Method::from_bytes(b"PROPFIND").unwrap();
+            let resp=match Client::new().request(propfind,"http://127.0.0.1:5000/items").header("Content-Type","text/plain").body("").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=207 || resp.text().map(|t| t!="PROPFIND ITEMS").unwrap_or(true){
+                return TestResult::FAILED(String::from("EXTENSION METHOD NOT DISPATCHED"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn error_handlers_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/nowhere").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=404 || resp.text().map(|t| t!=r#"{"missing":"/nowhere"}"#).unwrap_or(true){
+                return TestResult::FAILED(String::from("NOT FOUND HANDLER NOT USED"));
+            }
+            let resp=match Client::new().delete("http://127.0.0.1:5000/items").header("Content-Type","text/plain").body("").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=405 || resp.headers().get("Allow").is_none(){
+                return TestResult::FAILED(String::from("NO ALLOW HEADER FROM METHOD NOT ALLOWED HANDLER"));
+            }
+            if resp.text().map(|t| t!="METHOD NOT ALLOWED HERE").unwrap_or(true){
+                return TestResult::FAILED(String::from("METHOD NOT ALLOWED HANDLER NOT USED"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
+    #[test]
+    fn path_normalization_test(){
+        let _handle1=thread::spawn(||{
+            run_server();
+        });
+        thread::sleep(std::time::Duration::from_secs(1));
+        let handle2=thread::spawn(||{
+            let resp=match Client::new().get("http://127.0.0.1:5000/files//caf%C3%A9%20menu").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=200 || resp.text().map(|t| t!="café menu").unwrap_or(true){
+                return TestResult::FAILED(String::from("PATH NOT DECODED OR NORMALIZED"));
+            }
+            // An encoded slash stays inside it's segment, so a url built by url_for matches it's route.
+            let link=match Client::new().get("http://127.0.0.1:5000/link").send().and_then(|r| r.text()){
+                Ok(link)=>link,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            let resp=match Client::new().get(format!("http://127.0.0.1:5000{}",link)).send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if link!="/files/a%20b%2Fc" || resp.status().as_u16()!=200 || resp.text().map(|t| t!="a b/c").unwrap_or(true){
+                return TestResult::FAILED(format!("URL {} OF url_for NOT ROUTED",link));
+            }
+            let no_redirect=Client::builder().redirect(reqwest::redirect::Policy::none()).build().unwrap();
+            let resp=match no_redirect.get("http://127.0.0.1:5000/items/?page=2").send(){
+                Ok(R)=>R,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO SEND REQUEST. REASON :\n{}",e));
+                }
+            };
+            if resp.status().as_u16()!=308 || !(resp.headers().get("Location").map(|hv| hv.to_str().unwrap()).eq(&Some("/items?page=2"))){
+                return TestResult::FAILED(String::from("TRAILING SLASH NOT REDIRECTED"));
+            }
+            // reqwest resolves dot segments itself, so send the request by hand.
+            let mut stream=match std::net::TcpStream::connect("127.0.0.1:5000"){
+                Ok(s)=>s,
+                Err(e)=>{
+                    return TestResult::FAILED(format!("FAILED TO CONNECT. REASON :\n{}",e));
+                }
+            };
+            let _=stream.write_all(b"GET /files/../../etc HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n");
+            let mut resp=String::new();
+            let _=stream.read_to_string(&mut resp);
+            if !resp.starts_with("HTTP/1.1 400"){
+                return TestResult::FAILED(String::from("PATH TRAVERSAL NOT REJECTED"));
+            }
+            TestResult::PASSED
+        });
+        let res=handle2.join().expect("FAILED TO JOIN");
+        match res {
+            TestResult::FAILED(s)=>{
+                assert!(false,"{}",s);
+            },
+            TestResult::PASSED=>()
+        }
+    }
 }
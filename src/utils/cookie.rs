@@ -0,0 +1,322 @@
+//! ## Cookies
+//!
+//! Parsing of the `Cookie` request header and building of `Set-Cookie` response headers.
+use chrono::{DateTime, Utc};
+use std::{
+    fmt, io,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Value of the `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross site requests too. Browsers only accept it on a `Secure` cookie, so it's always sent with `Secure`.
+    None,
+}
+impl SameSite {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+/// ## Cookie
+/// A cookie to send with `HttpResponse::set_cookie`. Attributes are added with builder methods.
+///
+/// The name and value are sent exactly as given and `CookieJar::get` returns the value the same way, so a name or value
+/// with a byte a cookie can't hold is rejected rather than rewritten (RFC 6265 section 4.1.1): in the name anything but
+/// a token, in the value `;`, `,`, whitespace, `"`, `\`, control and non-ASCII characters. Encode such a value first,
+/// e.g. with percent-encoding, and decode it after reading it back. In the path and domain `;` and control characters
+/// are percent-encoded, so they never add attributes or split the header.
+/// ## Example
+/// ```no_run
+/// use rastapi::Request::{HttpRequest,PathParams};
+/// use rastapi::Response::{HttpResponse,create_response};
+/// use rastapi::utils::{ContentType,Cookie,SameSite};
+/// use std::time::Duration;
+///
+/// fn login(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+///     let mut resp=create_response("Logged in",200,ContentType::TEXT,false).unwrap();
+///     resp.set_cookie(
+///         Cookie::new("sid","abc123")
+///             .unwrap()
+///             .http_only()
+///             .secure()
+///             .same_site(SameSite::Lax)
+///             .max_age(Duration::from_secs(3600))
+///             .path("/"),
+///     );
+///     resp
+/// }
+/// fn logout(req:&HttpRequest,path_params:PathParams)->HttpResponse{
+///     let mut resp=create_response("Logged out",200,ContentType::TEXT,false).unwrap();
+///     // Path and domain must be the same as when the cookie was set.
+///     resp.remove_cookie(Cookie::named("sid").unwrap().path("/"));
+///     resp
+/// }
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    http_only: bool,
+    secure: bool,
+    same_site: Option<SameSite>,
+}
+/// Percent-encode the bytes of `s` that `allowed` rejects.
+fn encode_invalid(s: &str, allowed: fn(u8) -> bool) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if allowed(b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+/// Check that every byte of `s` is one `allowed` accepts. Fails with an error of kind `InvalidInput` naming `what` otherwise.
+fn check_octets(s: &str, allowed: fn(u8) -> bool, what: &str) -> io::Result<()> {
+    match s.bytes().find(|b| !allowed(*b)) {
+        Some(b) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Cookie {} {:?} can't hold the byte {:#04X}.", what, s, b),
+        )),
+        None => Ok(()),
+    }
+}
+/// A byte of a token, i.e. a visible ASCII character but a separator.
+fn is_token_octet(b: u8) -> bool {
+    b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b)
+}
+/// A byte of a cookie value, i.e. a visible ASCII character but `"`, `,`, `;` and `\`.
+fn is_cookie_octet(b: u8) -> bool {
+    b.is_ascii_graphic() && !b"\",;\\".contains(&b)
+}
+/// A byte of an attribute value, i.e. an ASCII character but `;` and control characters.
+fn is_attribute_octet(b: u8) -> bool {
+    (b.is_ascii_graphic() || b == b' ') && b != b';'
+}
+impl Cookie {
+    /// Create a session cookie, i.e. one without `Max-Age` or `Expires`.
+    /// Fails with an error of kind `InvalidInput` if the name is empty or isn't a token, or the value has a byte
+    /// a cookie can't hold, see **Cookie**.
+    pub fn new(name: &str, value: &str) -> io::Result<Self> {
+        if name.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Cookie name is empty."));
+        }
+        check_octets(name, is_token_octet, "name")?;
+        check_octets(value, is_cookie_octet, "value")?;
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        })
+    }
+    /// Create a cookie with an empty value, e.g. to pass to `HttpResponse::remove_cookie`. Fails like `new`.
+    pub fn named(name: &str) -> io::Result<Self> {
+        Self::new(name, "")
+    }
+    /// Only send the cookie for urls under `path`.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(encode_invalid(path, is_attribute_octet));
+        self
+    }
+    /// Send the cookie to `domain` and it's subdomains.
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(encode_invalid(domain, is_attribute_octet));
+        self
+    }
+    /// Keep the cookie for `max_age`, in whole seconds.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+    /// Keep the cookie until `expires`. `max_age` wins if both are set.
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+    /// Hide the cookie from javascript.
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+    /// Only send the cookie over https.
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+    /// Turn this cookie into one that makes the browser delete it, i.e. empty and already expired.
+    pub(crate) fn expired(mut self) -> Self {
+        self.value.clear();
+        self.max_age = Some(Duration::ZERO);
+        self.expires = Some(UNIX_EPOCH);
+        self
+    }
+}
+/// Formats the cookie as the value of a `Set-Cookie` header.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            let expires: DateTime<Utc> = expires.into();
+            write!(f, "; Expires={}", expires.format("%a, %d %b %Y %H:%M:%S GMT"))?;
+        }
+        if self.secure || self.same_site == Some(SameSite::None) {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        Ok(())
+    }
+}
+/// Cookies sent with a request, see `HttpRequest::cookies`.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct CookieJar {
+    /// Name value pairs in the order they were sent.
+    cookies: Vec<(String, String)>,
+}
+impl CookieJar {
+    /// Parse the values of `Cookie` headers, e.g. `sid=abc123; theme=dark`. Pairs without `=` are skipped
+    /// and double quotes around a value are removed.
+    pub(crate) fn parse<'a>(headers: impl Iterator<Item = &'a str>) -> Self {
+        let mut cookies = Vec::new();
+        for header in headers {
+            for pair in header.split(';') {
+                if let Some((name, value)) = pair.split_once('=') {
+                    let name = name.trim();
+                    let value = value.trim();
+                    let value = value
+                        .strip_prefix('"')
+                        .and_then(|v| v.strip_suffix('"'))
+                        .unwrap_or(value);
+                    if !name.is_empty() {
+                        cookies.push((name.to_string(), value.to_string()));
+                    }
+                }
+            }
+        }
+        Self { cookies }
+    }
+    /// Value of the cookie `name`. If the browser sent more than one cookie with this name, e.g. for different paths,
+    /// the first one is returned, it's the one with the longest path.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+    pub fn contains(&self, name: &str) -> bool {
+        self.cookies.iter().any(|(n, _)| n == name)
+    }
+    /// Iterate over the name value pairs in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+#[cfg(test)]
+mod cookietest {
+    use super::*;
+    #[test]
+    fn cookie_test() {
+        let cookie = Cookie::new("sid", "abc123")
+            .unwrap()
+            .http_only()
+            .secure()
+            .same_site(SameSite::Lax)
+            .max_age(Duration::from_secs(3600))
+            .path("/");
+        assert_eq!(
+            cookie.to_string(),
+            "sid=abc123; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(
+            Cookie::named("sid").unwrap().path("/").expired().to_string(),
+            "sid=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+        assert_eq!(
+            Cookie::new("a", "b").unwrap().same_site(SameSite::None).to_string(),
+            "a=b; Secure; SameSite=None"
+        );
+        let jar = CookieJar::parse(["sid=abc123; theme=\"dark\"", "sid=old;broken;lang=en"].into_iter());
+        assert_eq!(jar.get("sid"), Some("abc123"));
+        assert_eq!(jar.get("theme"), Some("dark"));
+        assert_eq!(jar.get("lang"), Some("en"));
+        assert!(!jar.contains("broken"));
+        assert_eq!(jar.len(), 4);
+    }
+    #[test]
+    fn invalid_cookie_test() {
+        // Bad bytes can't add attributes or split the response.
+        for (name, value) in [
+            ("sid", "abc; Domain=evil.com"),
+            ("sid", "abc\r\nSet-Cookie: admin=1"),
+            ("sid", "a b"),
+            ("sid", "caf\u{e9}"),
+            ("s;id=", "abc"),
+            ("a b", "abc"),
+            ("", "abc"),
+        ] {
+            assert_eq!(
+                Cookie::new(name, value).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "ACCEPTED {:?}={:?}",
+                name,
+                value
+            );
+        }
+        assert!(Cookie::named("s id").is_err());
+        assert_eq!(
+            Cookie::new("sid", "a").unwrap().path("/\r\n;x").to_string(),
+            "sid=a; Path=/%0D%0A%3Bx"
+        );
+        // Values come back from the jar exactly as they were set, a `%` included.
+        let sent = Cookie::new("msg", "a%20b").unwrap();
+        let jar = CookieJar::parse([sent.to_string().as_str()].into_iter());
+        assert_eq!(jar.get("msg"), Some(sent.value()));
+    }
+}
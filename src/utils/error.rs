@@ -11,12 +11,31 @@ pub enum ApiError<'a> {
     RequestBodyNotRead(Option<Cow<'a, str>>),
     InvalidContentLength(Option<Cow<'a, str>>),
     ContentTypeRequired(Option<Cow<'a, str>>),
-    MethodNotAllowed(Option<Cow<'a, str>>),
+    /// Message and the value of the `Allow` header, i.e. methods registered on the route.
+    MethodNotAllowed(Option<Cow<'a, str>>, String),
     MethodNotSupported(Option<Cow<'a, str>>),
     ResourceNotFound(Option<Cow<'a, str>>),
     PayloadTooLarge(Option<Cow<'a, str>>),
     RequestTimedout(Option<Cow<'a, str>>),
     ClientDisconnected(Option<Cow<'a, str>>),
+    RouteConflict(Option<Cow<'a, str>>),
+    InvalidRoutePattern(Option<Cow<'a, str>>),
+    /// Request path that can't be decoded or goes above the root.
+    InvalidPath(Option<Cow<'a, str>>),
+    /// Message and the location to redirect to, e.g. the same path with or without a trailing slash.
+    PermanentRedirect(Option<Cow<'a, str>>, String),
+    /// Request body that doesn't match it's content type, e.g. a multipart body without it's closing boundary.
+    MalformedBody(Option<Cow<'a, str>>),
+    /// A feature of HTTP/1.1 the server doesn't implement, e.g. a transfer coding other than chunked.
+    NotImplemented(Option<Cow<'a, str>>),
+    /// An `Expect` header other than `100-continue`.
+    ExpectationFailed(Option<Cow<'a, str>>),
+    /// Request line longer than the limit, see `RastAPI::set_metadata_limits`.
+    URITooLong(Option<Cow<'a, str>>),
+    /// Headers larger or more numerous than the limits, see `RastAPI::set_metadata_limits`.
+    HeaderFieldsTooLarge(Option<Cow<'a, str>>),
+    /// The disk quota of the incoming files directory is reached, see `RastAPI::set_incoming_files_quota`.
+    InsufficientStorage(Option<Cow<'a, str>>),
 }
 impl<'a> fmt::Display for ApiError<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -91,7 +110,7 @@ impl<'a> fmt::Display for ApiError<'a> {
                     write!(f, "Content type required.")
                 }
             }
-            Self::MethodNotAllowed(msg) => {
+            Self::MethodNotAllowed(msg, _) => {
                 if let Some(err_msg) = msg {
                     write!(f, "{}", err_msg)
                 } else {
@@ -133,6 +152,76 @@ impl<'a> fmt::Display for ApiError<'a> {
                     writeln!(f, "Client disconnected prematurely.")
                 }
             }
+            Self::RouteConflict(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Route conflicts with an already registered route.")
+                }
+            }
+            Self::InvalidRoutePattern(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Invalid route pattern.")
+                }
+            }
+            Self::InvalidPath(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Invalid request path.")
+                }
+            }
+            Self::PermanentRedirect(msg, location) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Moved to {}.", location)
+                }
+            }
+            Self::MalformedBody(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Malformed request body.")
+                }
+            }
+            Self::NotImplemented(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Not implemented.")
+                }
+            }
+            Self::ExpectationFailed(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Expectation failed.")
+                }
+            }
+            Self::URITooLong(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Request line too long.")
+                }
+            }
+            Self::HeaderFieldsTooLarge(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Request header fields too large.")
+                }
+            }
+            Self::InsufficientStorage(msg) => {
+                if let Some(err_msg) = msg {
+                    write!(f, "{}", err_msg)
+                } else {
+                    write!(f, "Disk quota of the incoming files directory reached.")
+                }
+            }
         }
     }
 }
@@ -0,0 +1,100 @@
+//! ## Headers
+//!
+//! Header map shared by requests and responses.
+
+/// HTTP headers of a request or a response.
+/// Names are compared case-insensitively, e.g. `content-length` finds `Content-Length`, but are kept as they were written.
+/// A header can have more than one value, e.g. `Set-Cookie`. Headers keep the order they were added in,
+/// and response headers are sent in that order.
+///
+/// ## Example
+/// ```no_run
+/// use rastapi::utils::HeaderMap;
+///
+/// let mut headers=HeaderMap::new();
+/// headers.insert("Cache-Control","no-cache");
+/// headers.append("Set-Cookie","theme=dark");
+/// headers.append("Set-Cookie","lang=en");
+/// assert_eq!(headers.get("cache-control"),Some("no-cache"));
+/// assert_eq!(headers.get_all("set-cookie"),vec!["theme=dark","lang=en"]);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeaderMap {
+    /// Name value pairs in the order they were added.
+    entries: Vec<(String, String)>,
+}
+impl HeaderMap {
+    /// Create an empty header map.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+    /// First value of the header `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, val)| val.as_str())
+    }
+    /// Every value of the header `name`, in the order they were added.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, val)| val.as_str())
+            .collect()
+    }
+    /// Check if there is a header named `name`.
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.entries.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))
+    }
+    /// Set the header `name` to `value`, replacing all of it's values.
+    /// The header keeps the position of it's first value, or is added at the end if it's new.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        let mut replaced = false;
+        self.entries.retain_mut(|(n, val)| {
+            if !n.eq_ignore_ascii_case(name) {
+                return true;
+            }
+            if replaced {
+                return false;
+            }
+            replaced = true;
+            *n = name.to_string();
+            *val = value.to_string();
+            true
+        });
+        if !replaced {
+            self.entries.push((name.to_string(), value.to_string()));
+        }
+    }
+    /// Add a value to the header `name`, keeping the values it already has.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+    /// Remove every value of the header `name`. Returns the values that were removed.
+    pub fn remove(&mut self, name: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.entries.retain(|(n, val)| {
+            if n.eq_ignore_ascii_case(name) {
+                removed.push(val.clone());
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+    /// Iterate over the name value pairs in the order they were added. A header with more than one value is yielded once per value.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+    /// Total number of name value pairs.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -2,7 +2,12 @@
 //! # Utils
 //! This is a utility module that helps by providing some utility functions,enums and structs.
 use error::ApiError;
+use std::borrow::Cow;
+pub use cookie::{Cookie, CookieJar, SameSite};
+pub use headers::HeaderMap;
+mod cookie;
 pub(crate) mod error;
+mod headers;
 pub mod load_env;
 pub(crate) mod threadpool;
 /// FileType is an enum that indicates the type of a file. It helps in sending file responses.
@@ -116,6 +121,10 @@ pub enum ContentType {
     ZIP,
     GZIP,
     EXE,
+    /// `multipart/form-data`, e.g. html forms with file inputs. See `HttpRequest::form`.
+    MULTIPART,
+    /// `application/x-www-form-urlencoded`, i.e. plain html form posts. See `HttpRequest::form_fields`.
+    URLENCODED,
     UNKNOWN,
 }
 impl ContentType {
@@ -140,11 +149,15 @@ impl ContentType {
             ContentType::ZIP => "Content-Type: application/zip\r\n",
             ContentType::GZIP => "Content-Type: application/gzip\r\n",
             ContentType::EXE => "Content-Type: application/x-msdownload\r\n",
+            ContentType::MULTIPART => "Content-Type: multipart/form-data\r\n",
+            ContentType::URLENCODED => "Content-Type: application/x-www-form-urlencoded\r\n",
             ContentType::UNKNOWN => "Content-Type: application/octet-stream\r\n",
         }
     }
+    /// Parameters after the media type, e.g. `; charset=utf-8` or `; boundary=...`, are ignored.
     pub(crate) fn from_header(header: &str) -> Result<Self, ApiError> {
-        let content_type = header.trim().to_lowercase();
+        let media_type = header.split(';').next().unwrap_or("");
+        let content_type = media_type.trim().to_lowercase();
         let res = match content_type.as_str() {
             "text/plain" => ContentType::TEXT,
             "application/json" => ContentType::JSON,
@@ -172,6 +185,8 @@ impl ContentType {
             "application/gzip" => ContentType::GZIP,
             "application/x-msdownload" => ContentType::EXE,
             "application/octet-stream" => ContentType::UNKNOWN,
+            "multipart/form-data" => ContentType::MULTIPART,
+            "application/x-www-form-urlencoded" => ContentType::URLENCODED,
             _ => {
                 return Err(ApiError::ContentNotSupported(None));
             }
@@ -199,7 +214,8 @@ impl ContentType {
             ContentType::ZIP => FileType::ZIP,
             ContentType::GZIP => FileType::GZIP,
             ContentType::EXE => FileType::EXE,
-            ContentType::UNKNOWN => FileType::UNKNOWN,
+            ContentType::URLENCODED => FileType::TEXT,
+            ContentType::MULTIPART | ContentType::UNKNOWN => FileType::UNKNOWN,
         }
     }
 }
@@ -418,27 +434,73 @@ impl HTTPCode {
     }
 }
 // Method is an enum that indicates HTTP methods.
-#[derive(PartialEq, Debug)]
+// Any other method made of uppercase letters, digits, `-` or `_` (e.g. PROPFIND, MKCOL) is an extension method.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub(crate) enum Method {
     GET,
+    HEAD,
     POST,
-    UPDATE,
     PUT,
     PATCH,
     DELETE,
+    OPTIONS,
+    TRACE,
+    CONNECT,
+    Extension(String),
 }
 impl Method {
     pub fn from_string(s: &str) -> Option<Self> {
         match s {
             "GET" => Some(Self::GET),
+            "HEAD" => Some(Self::HEAD),
             "POST" => Some(Self::POST),
-            "UPDATE" => Some(Self::UPDATE),
             "PUT" => Some(Self::PUT),
             "PATCH" => Some(Self::PATCH),
             "DELETE" => Some(Self::DELETE),
+            "OPTIONS" => Some(Self::OPTIONS),
+            "TRACE" => Some(Self::TRACE),
+            "CONNECT" => Some(Self::CONNECT),
+            _ if !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'-' || b == b'_') =>
+            {
+                Some(Self::Extension(s.to_string()))
+            }
             _ => None,
         }
     }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::GET => "GET",
+            Self::HEAD => "HEAD",
+            Self::POST => "POST",
+            Self::PUT => "PUT",
+            Self::PATCH => "PATCH",
+            Self::DELETE => "DELETE",
+            Self::OPTIONS => "OPTIONS",
+            Self::TRACE => "TRACE",
+            Self::CONNECT => "CONNECT",
+            Self::Extension(m) => m.as_str(),
+        }
+    }
+}
+/// TrailingSlash is the policy for urls that only differ from a registered route by a trailing slash,
+/// e.g. `/users/` when `/users` is registered.
+/// ## Example
+/// ```no_run
+/// use rastapi::RastAPI;
+/// use rastapi::utils::TrailingSlash;
+/// let mut app=RastAPI::new();
+/// app.set_trailing_slash_policy(TrailingSlash::Redirect);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrailingSlash {
+    /// `/users/` and `/users` are different urls. The default.
+    Strict,
+    /// Answer with a `308 Permanent Redirect` to the registered url.
+    Redirect,
+    /// Route to the registered url as if the trailing slash matched.
+    Ignore,
 }
 /// Protocol is an enum that indicates the protocol used by our clients to send request.
 /// Currently we only support two protocols, *HTTP/1.0* and *HTTP/1.1*.
@@ -462,3 +524,156 @@ impl Protocall {
         }
     }
 }
+/// Percent-encode `s` for use in a url. Only unreserved characters (`A-Z a-z 0-9 - . _ ~`) are kept as they are,
+/// plus `/` if `keep_slash` is set.
+pub(crate) fn percent_encode(s: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+/// Decode the percent-encoded bytes of `s`. Returns None for a malformed escape or if the result is not UTF-8.
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = bytes.get(idx + 1..idx + 3)?;
+            if !hex.iter().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+/// Percent-decode a raw url path segment by segment, then normalize it with `normalize_path`.
+/// A `/` or `%` decoded inside a segment stays encoded as `%2F` or `%25`, so an encoded slash never splits a segment.
+/// See `unescape_segment` to get the decoded value of a segment back.
+pub(crate) fn decode_path(raw: &str) -> Option<String> {
+    let mut path = String::with_capacity(raw.len());
+    for (idx, segment) in raw.split('/').enumerate() {
+        if idx > 0 {
+            path.push('/');
+        }
+        let decoded = percent_decode(segment)?;
+        path.push_str(&decoded.replace('%', "%25").replace('/', "%2F"));
+    }
+    normalize_path(&path)
+}
+/// Decoded value of a segment of a path given by `decode_path`, e.g. `a%2Fb` becomes `a/b`.
+pub(crate) fn unescape_segment(segment: &str) -> Cow<'_, str> {
+    if !segment.contains('%') {
+        return Cow::Borrowed(segment);
+    }
+    Cow::Owned(segment.replace("%2F", "/").replace("%25", "%"))
+}
+/// Percent-encode a path given by `decode_path` to send it back in a url, e.g. in a `Location` header.
+pub(crate) fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| percent_encode(&unescape_segment(segment), false))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+/// Collapse repeated slashes and resolve `.` and `..` segments of a decoded url path, e.g. `/a//./b/../c` becomes `/a/c`.
+/// A trailing slash is kept. Returns None if the path doesn't start with `/`, contains a NUL byte or `..` goes above the root.
+pub(crate) fn normalize_path(path: &str) -> Option<String> {
+    let rest = path.strip_prefix('/')?;
+    if path.contains('\0') {
+        return None;
+    }
+    let mut segments: Vec<&str> = Vec::new();
+    let mut trailing_slash = false;
+    for segment in rest.split('/') {
+        trailing_slash = true;
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop()?;
+            }
+            s => {
+                segments.push(s);
+                trailing_slash = false;
+            }
+        }
+    }
+    let mut normalized = String::with_capacity(path.len());
+    for segment in segments.iter() {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if trailing_slash || normalized.is_empty() {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+#[cfg(test)]
+mod utilstest {
+    use super::*;
+    #[test]
+    fn decode_path_test() {
+        assert_eq!(decode_path("/users/a%20b%2Fc").as_deref(), Some("/users/a b%2Fc"));
+        assert_eq!(decode_path("/a/%2E%2E/b%25").as_deref(), Some("/b%25"));
+        assert_eq!(unescape_segment("a b%2Fc"), "a b/c");
+        assert_eq!(unescape_segment("100%252F"), "100%2F", "ESCAPED PERCENT DECODED TWICE");
+        assert_eq!(encode_path("/users/a b%2Fc/"), "/users/a%20b%2Fc/");
+        assert!(decode_path("%2Fetc").is_none());
+        assert!(decode_path("/%2E%2E/etc").is_none(), "ENCODED DOT SEGMENT ABOVE THE ROOT");
+    }
+    #[test]
+    fn percent_decode_test() {
+        assert_eq!(percent_decode("/a%20b/caf%C3%A9").as_deref(), Some("/a b/café"));
+        assert_eq!(percent_decode("/plain+path").as_deref(), Some("/plain+path"));
+        assert!(percent_decode("/bad%2").is_none());
+        assert!(percent_decode("/bad%+1").is_none());
+        assert!(percent_decode("/not-utf8%FF").is_none());
+        assert_eq!(percent_decode(&percent_encode("a b/ü?", false)).as_deref(), Some("a b/ü?"));
+    }
+    #[test]
+    fn normalize_path_test() {
+        assert_eq!(normalize_path("/").as_deref(), Some("/"));
+        assert_eq!(normalize_path("/users//5").as_deref(), Some("/users/5"));
+        assert_eq!(normalize_path("/a/./b").as_deref(), Some("/a/b"));
+        assert_eq!(normalize_path("/a/../b").as_deref(), Some("/b"));
+        assert_eq!(normalize_path("/a/b/").as_deref(), Some("/a/b/"));
+        assert_eq!(normalize_path("/a/b/..").as_deref(), Some("/a/"));
+        assert_eq!(normalize_path("/a/..").as_deref(), Some("/"));
+        assert!(normalize_path("/..").is_none(), "TRAVERSAL ABOVE ROOT");
+        assert!(normalize_path("/a/../../b").is_none(), "TRAVERSAL ABOVE ROOT");
+        assert!(normalize_path("a/b").is_none());
+        assert!(normalize_path("/a\0b").is_none());
+    }
+    #[test]
+    fn header_map_test() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain");
+        headers.append("Set-Cookie", "a=1");
+        headers.insert("X-Request-Id", "1");
+        headers.append("set-cookie", "b=2");
+        assert_eq!(headers.get("content-type"), Some("text/plain"));
+        assert_eq!(headers.get_all("SET-COOKIE"), vec!["a=1", "b=2"]);
+        // Insert replaces every value and keeps the position of the first one.
+        headers.insert("CONTENT-TYPE", "application/json");
+        headers.insert("Set-Cookie", "c=3");
+        let names: Vec<(&str, &str)> = headers.iter().collect();
+        assert_eq!(
+            names,
+            vec![("CONTENT-TYPE", "application/json"), ("Set-Cookie", "c=3"), ("X-Request-Id", "1")]
+        );
+        assert_eq!(headers.remove("x-request-id"), vec![String::from("1")]);
+        assert!(!headers.contains_key("X-Request-Id"));
+        assert_eq!(headers.len(), 2);
+    }
+}